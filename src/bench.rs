@@ -0,0 +1,77 @@
+use std::{
+    sync::{Arc, Mutex, atomic::AtomicBool},
+    time::Instant,
+};
+
+use chessframe::board::Board;
+
+use crate::{
+    move_sorter::MoveSorter,
+    options::Options,
+    search::{Search, SearchLimits},
+    transposition_table::TranspositionTable,
+};
+
+pub const BENCH_DEPTH: u8 = 7;
+
+pub const BENCH_FENS: &[&str] = &[
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+    "8/8/4k3/8/2p5/8/B2K4/8 w - - 0 1",
+];
+
+// Searches every bench position to a fixed depth with fresh tables, so the node count only
+// changes when the search itself does.
+pub fn bench(depth: u8) -> usize {
+    let mut nodes = 0;
+
+    for fen in BENCH_FENS {
+        let mut search = Search::new(
+            Board::from_fen(fen),
+            SearchLimits {
+                depth: Some(depth),
+                ..Default::default()
+            },
+            Vec::new(),
+            Arc::new(TranspositionTable::with_size_mb(16)),
+            Arc::new(Mutex::new(MoveSorter::new())),
+            Arc::new(AtomicBool::new(false)),
+            Options::default(),
+        );
+
+        search.start_search();
+        nodes += search.nodes;
+    }
+
+    nodes
+}
+
+pub fn run(depth: u8) {
+    let timer = Instant::now();
+    let nodes = bench(depth);
+    let elapsed = timer.elapsed().as_millis() as usize;
+
+    println!(
+        "{} nodes {} nps",
+        nodes,
+        (nodes as f32 * 1000.0 / elapsed.max(1) as f32).round() as usize
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bench_is_deterministic() {
+        assert!(
+            bench(4) == bench(4),
+            "expected two bench runs to search exactly the same nodes"
+        );
+    }
+}
@@ -1,21 +1,52 @@
 use std::{
-    collections::HashSet,
     io,
     str::FromStr,
     sync::{
         Arc, Mutex,
         atomic::{AtomicBool, Ordering},
     },
-    thread,
+    thread::{self, JoinHandle},
 };
 
-use chessframe::{board::Board, color::Color, uci::*};
+use chessframe::{bitboard::EMPTY, board::Board, chess_move::ChessMove, color::Color, uci::*};
 
 use crate::{
-    move_sorter::MoveSorter, search::Search, time_management::TimeManagement,
-    transposition_table::TranspositionTable,
+    bench,
+    book::Book,
+    eval::Eval,
+    move_sorter::MoveSorter,
+    options::{Options, UCI_OPTIONS},
+    perft::{PerftTable, divide, perft, perft_hashed},
+    random::Random,
+    search::{Search, SearchLimits},
+    tablebase::Tablebases,
+    time_management::TimeManagement,
+    transposition_table::{Replacement, TranspositionTable},
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    Ongoing,
+    Checkmate,
+    Stalemate,
+    Repetition,
+    FiftyMove,
+    InsufficientMaterial,
+}
+
+impl GameResult {
+    pub fn description(&self) -> &'static str {
+        match self {
+            GameResult::Ongoing => "the game is still going",
+            GameResult::Checkmate => "checkmate",
+            GameResult::Stalemate => "stalemate",
+            GameResult::Repetition => "draw by threefold repetition",
+            GameResult::FiftyMove => "draw by the fifty move rule",
+            GameResult::InsufficientMaterial => "draw by insufficient material",
+        }
+    }
+}
+
 pub struct Engine {
     board: Board,
 
@@ -23,154 +54,585 @@ pub struct Engine {
     transposition_table: Arc<TranspositionTable>,
     move_sorter: Arc<Mutex<MoveSorter>>,
 
+    options: Options,
+    book: Option<Book>,
+    tablebases: Option<Arc<Tablebases>>,
+
+    search_thread: Option<JoinHandle<Option<ChessMove>>>,
     cancelled: Arc<AtomicBool>,
+    pondering: Arc<AtomicBool>,
     quitting: bool,
+
+    // Every line sent through send_command so far.
+    #[cfg(test)]
+    sent: Vec<String>,
 }
 
 impl Uci for Engine {
     fn send_command(&mut self, command: UciCommand) {
-        match command {
-            UciCommand::Id { name, author } => {
-                println!("id name {}", name);
-                println!("id author {}", author);
-            }
-            UciCommand::UciOk => {
-                println!("uciok");
-            }
-            UciCommand::ReadyOk => {
-                println!("readyok");
-            }
+        let line = match command {
+            UciCommand::Id { name, author } => format!("id name {}\nid author {}", name, author),
+            UciCommand::UciOk => "uciok".to_string(),
+            UciCommand::ReadyOk => "readyok".to_string(),
             UciCommand::BestMove { best_move, ponder } => {
                 if let Some(ponder) = ponder {
-                    println!("bestmove {} ponder {}", best_move, ponder);
+                    format!("bestmove {} ponder {}", best_move, ponder)
                 } else {
-                    println!("bestmove {}", best_move);
+                    format!("bestmove {}", best_move)
                 }
             }
-            UciCommand::Info(info) => {
-                println!("{}", info);
-            }
-            _ => {}
-        }
+            UciCommand::Info(info) => info.to_string(),
+            _ => return,
+        };
+
+        println!("{}", line);
+
+        #[cfg(test)]
+        self.sent.push(line);
     }
 
     fn read_command(&mut self) -> Option<UciCommand> {
         let mut line = String::new();
         io::stdin().read_line(&mut line).unwrap();
 
-        UciCommand::from_str(line.trim()).ok()
+        match UciCommand::from_str(line.trim()) {
+            Ok(command) => Some(command),
+            Err(_) => {
+                self.handle_custom_command(line.trim());
+                None
+            }
+        }
     }
 
     fn handle_command(&mut self) {
         if let Some(command) = self.read_command() {
-            match command {
-                UciCommand::Uci => {
-                    self.send_command(UciCommand::Id {
-                        name: "Ferrischess".to_string(),
-                        author: "Zirconium419122".to_string(),
-                    });
-                    self.send_command(UciCommand::UciOk);
+            self.process_command(command);
+        }
+    }
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine {
+            board: Board::default(),
+
+            repetition_table: Vec::new(),
+            transposition_table: Arc::new(Self::create_transposition_table(&Options::default())),
+            move_sorter: Arc::new(Mutex::new(MoveSorter::new())),
+
+            options: Options::default(),
+            book: None,
+            tablebases: None,
+
+            search_thread: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            pondering: Arc::new(AtomicBool::new(false)),
+            quitting: false,
+
+            #[cfg(test)]
+            sent: Vec::new(),
+        }
+    }
+
+    pub fn process_command(&mut self, command: UciCommand) {
+        match command {
+            UciCommand::Uci => {
+                self.send_command(UciCommand::Id {
+                    name: "Ferrischess".to_string(),
+                    author: "Zirconium419122".to_string(),
+                });
+                for option in UCI_OPTIONS {
+                    println!("{}", option);
                 }
-                UciCommand::Debug(debug) => {
-                    if debug {
-                        self.send_command(UciCommand::Info(Info {
-                            string: Some("Debug mode not supported yet!".to_string()),
-                            ..Default::default()
-                        }));
-                    }
+                self.send_command(UciCommand::UciOk);
+            }
+            UciCommand::Debug(debug) => self.options.debug = debug,
+            UciCommand::IsReady => self.send_command(UciCommand::ReadyOk),
+            // Nothing needs registering, the command is only accepted so it isn't reported as unknown.
+            UciCommand::Register { .. } => {}
+            UciCommand::SetOption { name, value } => {
+                let previous = self.options.clone();
+
+                if let Err(error) = self.options.set(&name, value.as_deref()) {
+                    self.send_info_string(error);
+                } else if self.options.hash != previous.hash
+                    || self.options.deterministic_hash != previous.deterministic_hash
+                {
+                    self.transposition_table = Arc::new(Self::create_transposition_table(&self.options));
+                } else if self.options.book_file != previous.book_file {
+                    self.load_book();
+                } else if self.options.syzygy_path != previous.syzygy_path {
+                    self.load_tablebases();
                 }
-                UciCommand::IsReady => self.send_command(UciCommand::ReadyOk),
-                UciCommand::UciNewGame => {
+            }
+            UciCommand::UciNewGame => {
+                self.board = Board::default();
+                self.repetition_table.clear();
+                self.stop_search();
+                self.transposition_table.clear();
+                self.move_sorter.lock().unwrap().clear();
+            }
+            UciCommand::Position { fen, moves } => {
+                if fen == "startpos" {
                     self.board = Board::default();
-                    self.repetition_table.clear();
-                    self.move_sorter.lock().unwrap().clear();
-                }
-                UciCommand::Position { fen, moves } => {
-                    if fen == "startpos" {
-                        self.board = Board::default();
-                    } else {
-                        self.board = Board::from_fen(&fen);
+                } else {
+                    match Self::try_from_fen(&fen) {
+                        Ok(board) => self.board = board,
+                        Err(error) => {
+                            self.send_info_string(format!("Invalid fen {}: {}, ignoring the position", fen, error));
+                            return;
+                        }
+                    }
+                };
+                self.repetition_table.clear();
+
+                for mv in moves.unwrap_or_default() {
+                    let Some(next_board) = Self::try_move(&self.board, &mv) else {
+                        self.send_info_string(format!("Illegal move {}, ignoring it and the moves after it", mv));
+                        break;
                     };
-                    self.repetition_table.clear();
 
-                    if let Some(moves) = moves {
-                        let board = &mut self.board;
+                    self.repetition_table.push(self.board.hash());
+                    self.board = next_board;
+                }
+            }
+            UciCommand::Go(Go {
+                depth,
+                wtime,
+                winc,
+                btime,
+                binc,
+                move_time,
+                moves_to_go,
+                infinite,
+                ponder,
+                search_moves,
+                mate,
+                nodes,
+                ..
+            }) => {
+                self.stop_search();
 
-                        for mv in moves {
-                            let zobrist_hash = board.hash();
-                            self.repetition_table.push(zobrist_hash);
+                // A drawn position may still be played on, but without a legal move there is nothing to search.
+                let result = self.game_result();
+                if result != GameResult::Ongoing {
+                    self.send_info_string(format!("Game over, {}", result.description()));
+                }
+                if matches!(result, GameResult::Checkmate | GameResult::Stalemate) {
+                    println!("bestmove 0000");
+                    return;
+                }
 
-                            let mv = board.infer_move(&mv).unwrap();
+                // A ponder or infinite search has to wait for the GUI, so only a plain go is answered from the book.
+                if !ponder
+                    && !infinite
+                    && search_moves.is_none()
+                    && let Some(book_move) = self.book_move()
+                {
+                    println!("bestmove {}", book_move);
+                    return;
+                }
 
-                            let _ = board.make_move(mv);
-                        }
+                self.cancelled.store(false, Ordering::Relaxed);
+                self.pondering.store(ponder, Ordering::Relaxed);
+
+                self.transposition_table.new_search();
+
+                let repetition_table = self.repetition_history();
+                let transposition_table = self.transposition_table.clone();
+                let move_sorter = self.move_sorter.clone();
+
+                let (time, time_inc) = if self.board.side_to_move == Color::White {
+                    (wtime, winc)
+                } else {
+                    (btime, binc)
+                };
+
+                let board = self.board;
+                let cancelled = self.cancelled.clone();
+                let options = self.options.clone();
+                let tablebases = self.tablebases.clone();
+
+                let time_management = TimeManagement::new(move_time, time, time_inc, moves_to_go)
+                    .with_overhead(self.options.move_overhead);
+
+                // A bare go would otherwise search until stopped.
+                let unlimited = time_management == TimeManagement::None
+                    && !infinite
+                    && !ponder
+                    && mate.is_none()
+                    && nodes.is_none();
+
+                let depth = depth
+                    .map(|depth| depth.min(Search::MAX_PLY as usize) as u8)
+                    .or(unlimited.then_some(self.options.default_depth));
+
+                let limits = SearchLimits {
+                    depth,
+                    time_management,
+                    infinite,
+                    pondering: self.pondering.clone(),
+                    search_moves: search_moves.unwrap_or_default(),
+                    mate: mate.map(|mate| mate.min(u8::MAX as usize) as u8),
+                    nodes,
+                };
+
+                self.search_thread = Some(thread::spawn(move || {
+                    // Lazy SMP, the helpers only share what they find through the transposition table.
+                    let helpers = (1..options.threads)
+                        .map(|thread_id| {
+                            let mut helper = Search::new(
+                                board,
+                                limits.for_helper(),
+                                repetition_table.clone(),
+                                transposition_table.clone(),
+                                Arc::new(Mutex::new(MoveSorter::new())),
+                                cancelled.clone(),
+                                options.clone(),
+                            );
+                            helper.thread_id = thread_id;
+                            helper.tablebases = tablebases.clone();
+
+                            thread::spawn(move || helper.start_search())
+                        })
+                        .collect::<Vec<_>>();
+
+                    let mut search = Search::new(
+                        board,
+                        limits,
+                        repetition_table,
+                        transposition_table,
+                        move_sorter,
+                        cancelled.clone(),
+                        options,
+                    );
+                    search.tablebases = tablebases;
+
+                    let best_move = search.start_search();
+
+                    cancelled.store(true, Ordering::Relaxed);
+                    for helper in helpers {
+                        let _ = helper.join();
                     }
+
+                    best_move
+                }));
+            }
+            UciCommand::Stop => {
+                self.stop_search();
+            }
+            UciCommand::PonderHit => {
+                if self.search_thread.is_some() && self.pondering.load(Ordering::Relaxed) {
+                    self.pondering.store(false, Ordering::Relaxed);
+                } else {
+                    self.send_info_string("Received ponderhit without a ponder search, ignoring it".to_string());
                 }
-                UciCommand::Go(Go {
-                    depth,
-                    wtime,
-                    winc,
-                    btime,
-                    binc,
-                    move_time,
-                    ..
-                }) => {
-                    self.cancelled.store(false, Ordering::Relaxed);
-
-                    let mut repetition_table = HashSet::from_iter(self.repetition_table.clone());
-                    repetition_table.reserve(16);
-                    let transposition_table = self.transposition_table.clone();
-                    let move_sorter = self.move_sorter.clone();
-
-                    let (time, time_inc) = if self.board.side_to_move == Color::White {
-                        (wtime, winc)
-                    } else {
-                        (btime, binc)
-                    };
+            }
+            UciCommand::Quit => {
+                self.stop_search();
+                self.quitting = true;
+            }
+            _ => {}
+        }
+    }
+
+    // Commands outside of the UCI protocol, meant for debugging from a terminal.
+    pub fn handle_custom_command(&mut self, line: &str) {
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some(command @ ("perft" | "divide")) => {
+                let Some(depth) = tokens.next().and_then(|depth| depth.parse::<u8>().ok()) else {
+                    self.send_info_string(format!("Expected a depth after {}", command));
+                    return;
+                };
+
+                // `perft <depth> hash` trades Hash megabytes for speed on deep counts.
+                let hashed = tokens.next() == Some("hash");
+
+                let nodes = if command == "divide" {
+                    let counts = divide(&self.board, depth);
+                    for (mv, nodes) in &counts {
+                        println!("{}: {}", mv, nodes);
+                    }
+
+                    counts.iter().map(|(_, nodes)| nodes).sum()
+                } else if hashed {
+                    perft_hashed(&self.board, depth, &mut PerftTable::with_size_mb(self.options.hash))
+                } else {
+                    perft(&self.board, depth)
+                };
+
+                println!();
+                println!("Nodes searched: {}", nodes);
+            }
+            Some("bench") => {
+                let depth = tokens
+                    .next()
+                    .and_then(|depth| depth.parse::<u8>().ok())
+                    .unwrap_or(bench::BENCH_DEPTH);
+
+                bench::run(depth);
+            }
+            Some("d") => println!("{}", self.board_description()),
+            Some("eval") => {
+                let (white, side_to_move) = self.static_eval();
+
+                println!("{}", Eval::new(&self.board).eval_detailed());
+                println!();
+                println!("White: {}", white);
+                println!("Side to move: {}", side_to_move);
+            }
+            _ => {}
+        }
+    }
+
+    // Board::from_fen trusts its input, so a malformed FEN from the GUI is caught here first.
+    pub fn try_from_fen(fen: &str) -> Result<Board, String> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 || fields.len() > 6 {
+            return Err(format!("expected 4 to 6 fields, got {}", fields.len()));
+        }
+
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(format!("expected 8 ranks, got {}", ranks.len()));
+        }
+
+        // Pieces by rank and file from a1, with '.' for an empty square.
+        let mut squares = [['.'; 8]; 8];
+
+        for (index, rank) in ranks.iter().enumerate() {
+            let mut files = Vec::with_capacity(8);
 
-                    let board = self.board;
-                    let cancelled = self.cancelled.clone();
-
-                    thread::spawn(move || {
-                        let mut search = Search::new(
-                            board,
-                            depth.map(|depth| depth as u8),
-                            TimeManagement::new(move_time, time, time_inc),
-                            repetition_table,
-                            transposition_table,
-                            move_sorter,
-                            cancelled,
-                        );
-
-                        search.start_search();
-                    });
+            for c in rank.chars() {
+                match c {
+                    '1'..='8' => files.extend(std::iter::repeat_n('.', c.to_digit(10).unwrap() as usize)),
+                    'p' | 'n' | 'b' | 'r' | 'q' | 'k' | 'P' | 'N' | 'B' | 'R' | 'Q' | 'K' => files.push(c),
+                    _ => return Err(format!("unexpected character {} in the pieces", c)),
                 }
-                UciCommand::Stop => self.cancelled.store(true, Ordering::Relaxed),
-                UciCommand::Quit => self.quitting = true,
-                _ => {}
             }
+
+            if files.len() != 8 {
+                return Err(format!("rank {} doesn't have 8 files", rank));
+            }
+            squares[7 - index].copy_from_slice(&files);
+        }
+
+        for king in ['K', 'k'] {
+            if fields[0].matches(king).count() != 1 {
+                return Err(format!("expected exactly one {}", king));
+            }
+        }
+
+        if squares[0].iter().chain(&squares[7]).any(|&piece| piece.eq_ignore_ascii_case(&'p')) {
+            return Err("pawns can't stand on the first or last rank".to_string());
+        }
+
+        if fields[1] != "w" && fields[1] != "b" {
+            return Err(format!("unexpected side to move {}", fields[1]));
+        }
+
+        if fields[2] != "-" && !fields[2].chars().all(|c| "KQkq".contains(c)) {
+            return Err(format!("unexpected castling rights {}", fields[2]));
         }
+
+        // Each right needs the king and its rook still on their starting squares.
+        for (right, rank, rook_file, king, rook) in
+            [('K', 0, 7, 'K', 'R'), ('Q', 0, 0, 'K', 'R'), ('k', 7, 7, 'k', 'r'), ('q', 7, 0, 'k', 'r')]
+        {
+            if fields[2].contains(right) && (squares[rank][4] != king || squares[rank][rook_file] != rook) {
+                return Err(format!("castling right {} without the king and rook in place", right));
+            }
+        }
+
+        // The pawn that just moved two squares stands in front of the en passant square, which it passed over.
+        let en_passant = fields[3].as_bytes();
+        if fields[3] != "-" {
+            let (rank, pawn, forward) = if fields[1] == "w" { (b'6', 'p', -1) } else { (b'3', 'P', 1) };
+
+            if en_passant.len() != 2 || !(b'a'..=b'h').contains(&en_passant[0]) || en_passant[1] != rank {
+                return Err(format!("unexpected en passant square {}", fields[3]));
+            }
+
+            let file = (en_passant[0] - b'a') as usize;
+            let rank = (rank - b'1') as isize;
+            if squares[(rank + forward) as usize][file] != pawn
+                || squares[rank as usize][file] != '.'
+                || squares[(rank - forward) as usize][file] != '.'
+            {
+                return Err(format!("no pawn just passed over the en passant square {}", fields[3]));
+            }
+        }
+
+        if fields[4..].iter().any(|clock| clock.parse::<u32>().is_err()) {
+            return Err("unexpected move counters".to_string());
+        }
+
+        // The side that just moved can't have left its own king in check.
+        let other_side = if fields[1] == "w" { "b" } else { "w" };
+        if Board::from_fen(&format!("{} {} {} - 0 1", fields[0], other_side, fields[2])).in_check() {
+            return Err("the side not to move is in check".to_string());
+        }
+
+        Ok(Board::from_fen(fen))
     }
-}
 
-impl Engine {
-    const TRANSPOSITIONTABLE_SIZE: usize = 64;
+    // The GUI may send anything, only moves that are legal in the current position get played.
+    fn try_move(board: &Board, mv: &str) -> Option<Board> {
+        let mv = board.infer_move(mv).ok()?;
 
-    pub fn new() -> Engine {
-        Engine {
-            board: Board::default(),
+        if !board.generate_moves_vec(!EMPTY).contains(&mv) {
+            return None;
+        }
 
-            repetition_table: Vec::new(),
-            transposition_table: Arc::new(TranspositionTable::with_size_mb(
-                Engine::TRANSPOSITIONTABLE_SIZE,
-            )),
-            move_sorter: Arc::new(Mutex::new(MoveSorter::new())),
+        board.make_move_new(mv).ok()
+    }
 
-            cancelled: Arc::new(AtomicBool::new(false)),
-            quitting: false,
+    // The board as ASCII art followed by the state a FEN doesn't show at a glance, like Stockfish's d.
+    fn board_description(&self) -> String {
+        let fen = self.board.to_fen();
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+
+        let mut description = String::new();
+
+        for rank in fields[0].split('/') {
+            description.push_str(" +---+---+---+---+---+---+---+---+\n |");
+
+            for c in rank.chars() {
+                match c.to_digit(10) {
+                    Some(empty) => description.push_str(&"   |".repeat(empty as usize)),
+                    None => description.push_str(&format!(" {} |", c)),
+                }
+            }
+
+            description.push('\n');
+        }
+        description.push_str(" +---+---+---+---+---+---+---+---+\n\n");
+
+        let side_to_move = if self.board.side_to_move == Color::White { "white" } else { "black" };
+
+        description.push_str(&format!("Fen: {}\n", fen));
+        description.push_str(&format!("Side to move: {}\n", side_to_move));
+        description.push_str(&format!("Castling: {}\n", fields[2]));
+        description.push_str(&format!("Key: {:016X}\n", self.board.hash()));
+        description.push_str(&format!("\n{}\n\n", Eval::new(&self.board).eval_detailed()));
+        description.push_str(&format!("Eval: {}", Eval::new(&self.board).eval()));
+
+        description
+    }
+
+    // Centipawns from white's point of view and from the side to move's, in that order.
+    fn static_eval(&self) -> (i32, i32) {
+        let side_to_move = Eval::new(&self.board).eval();
+
+        if self.board.side_to_move == Color::White {
+            (side_to_move, side_to_move)
+        } else {
+            (-side_to_move, side_to_move)
+        }
+    }
+
+    // Mate and stalemate end the game outright, so they go before the draws that could be claimed.
+    pub fn game_result(&self) -> GameResult {
+        let has_legal_move = self
+            .board
+            .generate_moves_vec(!EMPTY)
+            .into_iter()
+            .any(|mv| self.board.make_move_new(mv).is_ok());
+
+        if !has_legal_move {
+            if self.board.in_check() {
+                GameResult::Checkmate
+            } else {
+                GameResult::Stalemate
+            }
+        } else if Eval::new(&self.board).insufficient_material() {
+            GameResult::InsufficientMaterial
+        } else if self.board.is_fifty_move() {
+            GameResult::FiftyMove
+        } else if self.repetition_table.iter().filter(|&&hash| hash == self.board.hash()).count() >= 2 {
+            GameResult::Repetition
+        } else {
+            GameResult::Ongoing
+        }
+    }
+
+    // Every position played before the current one, the search adds the current position itself.
+    fn repetition_history(&self) -> Vec<u64> {
+        let mut repetition_table = self.repetition_table.clone();
+        repetition_table.reserve(Search::MAX_PLY as usize);
+
+        repetition_table
+    }
+
+    // Returns once the running search, if any, has printed its bestmove.
+    fn stop_search(&mut self) -> Option<ChessMove> {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.pondering.store(false, Ordering::Relaxed);
+
+        self.search_thread.take().and_then(|search_thread| search_thread.join().unwrap())
+    }
+
+    fn load_book(&mut self) {
+        self.book = None;
+
+        if self.options.book_file.is_empty() {
+            return;
+        }
+
+        match Book::open(&self.options.book_file) {
+            Ok(book) => self.book = Some(book),
+            Err(error) => {
+                self.send_info_string(format!("Could not open book {}: {}", self.options.book_file, error));
+            }
         }
     }
 
+    fn load_tablebases(&mut self) {
+        self.tablebases = None;
+
+        if self.options.syzygy_path.is_empty() {
+            return;
+        }
+
+        match Tablebases::open(&self.options.syzygy_path) {
+            Ok(tablebases) => self.tablebases = Some(Arc::new(tablebases)),
+            Err(error) => self.send_info_string(format!("Could not load tablebases: {}", error)),
+        }
+    }
+
+    fn book_move(&self) -> Option<ChessMove> {
+        if !self.options.own_book {
+            return None;
+        }
+
+        let mut random = if self.options.random_seed != 0 {
+            Random::new(self.options.random_seed)
+        } else {
+            Random::from_time()
+        };
+
+        self.book.as_ref()?.pick(&self.board, &mut random)
+    }
+
+    fn create_transposition_table(options: &Options) -> TranspositionTable {
+        let mut transposition_table = TranspositionTable::with_size_mb(options.hash);
+
+        if options.deterministic_hash {
+            transposition_table.set_replacement(Replacement::Always);
+        }
+
+        transposition_table
+    }
+
+    fn send_info_string(&mut self, string: String) {
+        self.send_command(UciCommand::Info(Info {
+            string: Some(string),
+            ..Default::default()
+        }));
+    }
+
     pub fn run(&mut self) {
         loop {
             self.handle_command();
@@ -181,3 +643,463 @@ impl Engine {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chessframe::square::Square;
+
+    use super::*;
+    use crate::{book::polyglot_key, transposition_table::Bound};
+
+    #[test]
+    fn illegal_position_moves_are_skipped() {
+        let mut engine = Engine::new();
+
+        engine.process_command(UciCommand::Position {
+            fen: "startpos".to_string(),
+            moves: Some(vec!["e2e4".to_string(), "e2e5".to_string(), "garbage".to_string()]),
+        });
+
+        let expected = Board::default().make_move_new(ChessMove::new(Square::E2, Square::E4)).unwrap();
+        assert!(engine.board.hash() == expected.hash(), "expected only the legal e2e4 to be played");
+        assert!(engine.repetition_table.len() == 1, "expected only the played move in the repetition table");
+
+        engine.process_command(UciCommand::IsReady);
+        assert!(!engine.quitting, "expected the engine to stay alive");
+    }
+
+    #[test]
+    fn terminal_positions_are_reported() {
+        let mut engine = Engine::new();
+
+        for (fen, expected) in [
+            ("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3", GameResult::Checkmate),
+            ("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", GameResult::Stalemate),
+            ("8/8/4k3/8/8/4K3/8/R7 w - - 100 80", GameResult::FiftyMove),
+            ("8/8/4k3/8/8/2B1K3/8/8 w - - 0 1", GameResult::InsufficientMaterial),
+            ("8/8/4k3/8/8/4K3/8/R7 w - - 0 1", GameResult::Ongoing),
+        ] {
+            engine.process_command(UciCommand::Position {
+                fen: fen.to_string(),
+                moves: None,
+            });
+
+            assert!(engine.game_result() == expected, "expected {} in {}", expected.description(), fen);
+        }
+
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"].map(str::to_string);
+        engine.process_command(UciCommand::Position {
+            fen: "startpos".to_string(),
+            moves: Some([shuffle.clone(), shuffle].concat()),
+        });
+        assert!(engine.game_result() == GameResult::Repetition, "expected the third start position to repeat");
+    }
+
+    #[test]
+    fn go_on_a_mated_position_does_not_search() {
+        let mut engine = Engine::new();
+
+        engine.process_command(UciCommand::Position {
+            fen: "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3".to_string(),
+            moves: None,
+        });
+        engine.process_command(UciCommand::Go(Go {
+            depth: Some(3),
+            ..Default::default()
+        }));
+
+        assert!(engine.search_thread.is_none(), "expected no search to be started on a finished game");
+    }
+
+    #[test]
+    fn malformed_fens_are_rejected() {
+        for fen in [
+            "not-a-fen",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQ1BNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1",
+            "rnbqkbnp/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq e3 0 1",
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR b KQkq - 1 3",
+        ] {
+            assert!(Engine::try_from_fen(fen).is_err(), "expected {} to be rejected", fen);
+        }
+
+        assert!(
+            Engine::try_from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").is_ok(),
+            "expected a valid fen to load"
+        );
+    }
+
+    #[test]
+    fn invalid_fen_keeps_the_previous_position() {
+        let mut engine = Engine::new();
+
+        engine.process_command(UciCommand::Position {
+            fen: "startpos".to_string(),
+            moves: Some(vec!["e2e4".to_string()]),
+        });
+        let previous = engine.board;
+
+        engine.process_command(UciCommand::Position {
+            fen: "not-a-fen".to_string(),
+            moves: None,
+        });
+        assert!(engine.board.hash() == previous.hash(), "expected the board before the invalid fen");
+        assert!(engine.repetition_table.len() == 1, "expected the game history to be kept");
+
+        engine.process_command(UciCommand::Go(Go {
+            depth: Some(3),
+            ..Default::default()
+        }));
+
+        let best_move = engine.search_thread.take().unwrap().join().unwrap().unwrap();
+        assert!(
+            previous.make_move_new(best_move).is_ok(),
+            "expected a legal move for black in the previous position"
+        );
+    }
+
+    #[test]
+    fn bare_go_stops_at_the_default_depth() {
+        let mut engine = Engine::new();
+
+        engine.process_command(UciCommand::SetOption {
+            name: "DefaultDepth".to_string(),
+            value: Some("2".to_string()),
+        });
+        engine.process_command(UciCommand::Go(Go::default()));
+
+        let search_thread = engine.search_thread.take().unwrap();
+        for _ in 0..100 {
+            if search_thread.is_finished() {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(50));
+        }
+
+        assert!(search_thread.is_finished(), "expected a bare go to finish on its own");
+        assert!(
+            engine.board.make_move_new(search_thread.join().unwrap().unwrap()).is_ok(),
+            "expected a legal move from the default depth"
+        );
+    }
+
+    #[test]
+    fn go_nodes_finishes_without_a_stop() {
+        let mut engine = Engine::new();
+
+        engine.process_command(UciCommand::Go(Go {
+            nodes: Some(100_000),
+            ..Default::default()
+        }));
+
+        let search_thread = engine.search_thread.take().unwrap();
+        for _ in 0..200 {
+            if search_thread.is_finished() {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(50));
+        }
+
+        assert!(search_thread.is_finished(), "expected the node budget to end the search");
+        assert!(
+            engine.board.make_move_new(search_thread.join().unwrap().unwrap()).is_ok(),
+            "expected a legal move within the node budget"
+        );
+    }
+
+    #[test]
+    fn two_threads_find_a_legal_move() {
+        let stored_keys = |threads: &str| {
+            let mut engine = Engine::new();
+
+            engine.process_command(UciCommand::SetOption {
+                name: "Threads".to_string(),
+                value: Some(threads.to_string()),
+            });
+            engine.process_command(UciCommand::Go(Go {
+                depth: Some(5),
+                ..Default::default()
+            }));
+
+            let best_move = engine.search_thread.take().unwrap().join().unwrap().unwrap();
+            assert!(engine.board.make_move_new(best_move).is_ok(), "expected a legal move with {} threads", threads);
+
+            engine
+                .transposition_table
+                .entries()
+                .iter()
+                .filter(|entry| entry.bound != Bound::None)
+                .map(|entry| entry.zobrist)
+                .collect::<HashSet<u64>>()
+        };
+
+        let main_thread = stored_keys("1");
+        let with_helper = stored_keys("2");
+
+        assert!(
+            with_helper.iter().any(|zobrist| !main_thread.contains(zobrist)),
+            "expected the helper to store positions the main thread alone never does"
+        );
+    }
+
+    #[test]
+    fn eval_command_scores_from_white() {
+        let mut engine = Engine::new();
+
+        let (white, _) = engine.static_eval();
+        assert!(white.abs() < 50, "expected the start position to be about equal, got {}", white);
+
+        engine.board = Board::from_fen("rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1");
+
+        let (white, side_to_move) = engine.static_eval();
+        assert!((700..1100).contains(&white), "expected white to be about a queen up, got {}", white);
+        assert!(side_to_move == -white, "expected black to move to see the score negated");
+    }
+
+    #[test]
+    fn d_command_fen_round_trips() {
+        let mut engine = Engine::new();
+        engine.board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+
+        let description = engine.board_description();
+        let fen = engine.board.to_fen();
+
+        assert!(description.contains(&format!("Fen: {}", fen)), "expected the FEN in the description");
+        assert!(description.contains("Castling: KQkq"), "expected the castling rights");
+        assert!(
+            Board::from_fen(&fen).hash() == engine.board.hash(),
+            "expected the printed FEN to load back into the same position"
+        );
+    }
+
+    #[test]
+    fn spurious_ponderhit_is_ignored() {
+        let mut engine = Engine::new();
+
+        engine.process_command(UciCommand::PonderHit);
+        engine.process_command(UciCommand::IsReady);
+
+        assert!(
+            !engine.quitting && !engine.cancelled.load(Ordering::Relaxed),
+            "expected a ponderhit without a ponder search to leave the engine untouched"
+        );
+        assert!(
+            engine.sent.len() == 2
+                && engine.sent[0].contains("string Received ponderhit without a ponder search")
+                && engine.sent[1] == "readyok",
+            "expected the ponderhit to be reported as ignored, got {:?}",
+            engine.sent
+        );
+    }
+
+    #[test]
+    fn hash_option_resizes_the_transposition_table() {
+        let mut engine = Engine::new();
+        let default_capacity = engine.transposition_table.capacity();
+
+        engine.process_command(UciCommand::SetOption {
+            name: "Hash".to_string(),
+            value: Some("1".to_string()),
+        });
+
+        assert!(
+            engine.transposition_table.capacity() < default_capacity,
+            "expected a 1MB table to hold fewer entries than the default"
+        );
+
+        engine.process_command(UciCommand::Go(Go {
+            depth: Some(4),
+            ..Default::default()
+        }));
+
+        let best_move = engine.search_thread.take().unwrap().join().unwrap().unwrap();
+        assert!(
+            engine.board.make_move_new(best_move).is_ok(),
+            "expected a legal move from the smaller table"
+        );
+    }
+
+    #[test]
+    fn transposition_table_persists_until_a_new_game() {
+        let mut engine = Engine::new();
+
+        engine.process_command(UciCommand::Position {
+            fen: "startpos".to_string(),
+            moves: Some(vec!["e2e4".to_string(), "e7e5".to_string()]),
+        });
+        engine.process_command(UciCommand::Go(Go {
+            depth: Some(4),
+            ..Default::default()
+        }));
+        let best_move = engine.search_thread.take().unwrap().join().unwrap().unwrap();
+
+        let next_board = engine.board.make_move_new(best_move).unwrap();
+        engine.process_command(UciCommand::Position {
+            fen: "startpos".to_string(),
+            moves: Some(vec!["e2e4".to_string(), "e7e5".to_string(), best_move.to_string()]),
+        });
+
+        assert!(
+            engine.board.hash() == next_board.hash()
+                && engine.transposition_table.probe(engine.board.hash()).is_some(),
+            "expected the next search to start from entries of the previous one"
+        );
+
+        engine.process_command(UciCommand::UciNewGame);
+
+        assert!(
+            engine.transposition_table.probe(next_board.hash()).is_none(),
+            "expected a new game to clear the transposition table"
+        );
+    }
+
+    #[test]
+    fn position_moves_are_recorded_for_repetitions() {
+        let mut engine = Engine::new();
+
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8"];
+        engine.process_command(UciCommand::Position {
+            fen: "startpos".to_string(),
+            moves: Some(shuffle.iter().map(|mv| mv.to_string()).collect()),
+        });
+
+        let history = engine.repetition_history();
+        let repeated = engine.board.make_move_new(engine.board.infer_move("g1f3").unwrap()).unwrap();
+
+        assert!(
+            history.contains(&Board::default().hash()) && history.contains(&repeated.hash()),
+            "expected every position of the game to be in the repetition history"
+        );
+    }
+
+    #[test]
+    fn own_book_answers_go_without_searching() {
+        // One entry for the start position, e2e4 with weight 1.
+        let mut bytes = polyglot_key(&Board::default()).to_be_bytes().to_vec();
+        bytes.extend_from_slice(&[0x03, 0x1C, 0, 1, 0, 0, 0, 0]);
+
+        let path = std::env::temp_dir().join(format!("ferrischess-book-{}.bin", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+
+        let mut engine = Engine::new();
+        for (name, value) in [("BookFile", path.to_str().unwrap()), ("OwnBook", "true")] {
+            engine.process_command(UciCommand::SetOption {
+                name: name.to_string(),
+                value: Some(value.to_string()),
+            });
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(engine.book_move() == Some(ChessMove::new(Square::E2, Square::E4)), "expected the book move");
+
+        engine.process_command(UciCommand::Go(Go {
+            depth: Some(3),
+            ..Default::default()
+        }));
+        assert!(engine.search_thread.is_none(), "expected the book move to be played without a search");
+
+        engine.process_command(UciCommand::Position {
+            fen: "startpos".to_string(),
+            moves: Some(vec!["d2d4".to_string()]),
+        });
+        engine.process_command(UciCommand::Go(Go {
+            depth: Some(3),
+            ..Default::default()
+        }));
+        assert!(engine.stop_search().is_some(), "expected a search once the position is out of the book");
+    }
+
+    #[test]
+    fn ponder_search_ignores_the_clock_until_ponderhit() {
+        let mut engine = Engine::new();
+
+        engine.process_command(UciCommand::Go(Go {
+            ponder: true,
+            move_time: Some(50),
+            ..Default::default()
+        }));
+
+        thread::sleep(std::time::Duration::from_millis(200));
+        assert!(
+            !engine.search_thread.as_ref().unwrap().is_finished(),
+            "expected a ponder search to keep going past its movetime"
+        );
+
+        engine.process_command(UciCommand::PonderHit);
+
+        let started = std::time::Instant::now();
+        while !engine.search_thread.as_ref().unwrap().is_finished() {
+            assert!(
+                started.elapsed() < std::time::Duration::from_secs(2),
+                "expected the search to finish on its clock after ponderhit"
+            );
+            thread::sleep(std::time::Duration::from_millis(5));
+        }
+
+        assert!(engine.stop_search().is_some());
+    }
+
+    #[test]
+    fn go_ponder_holds_back_the_bestmove() {
+        let mut engine = Engine::new();
+
+        engine.process_command(UciCommand::SetOption {
+            name: "Ponder".to_string(),
+            value: Some("true".to_string()),
+        });
+        engine.process_command(UciCommand::Go(Go {
+            ponder: true,
+            depth: Some(1),
+            ..Default::default()
+        }));
+
+        thread::sleep(std::time::Duration::from_millis(100));
+        assert!(
+            !engine.search_thread.as_ref().unwrap().is_finished(),
+            "expected a finished ponder search to wait instead of sending bestmove"
+        );
+
+        engine.process_command(UciCommand::Stop);
+        assert!(engine.search_thread.is_none(), "expected stop to collect the bestmove");
+    }
+
+    #[test]
+    fn stopped_ponder_search_still_ends_with_a_best_move() {
+        let mut engine = Engine::new();
+
+        engine.process_command(UciCommand::Go(Go {
+            ponder: true,
+            ..Default::default()
+        }));
+
+        thread::sleep(std::time::Duration::from_millis(100));
+        engine.process_command(UciCommand::Stop);
+
+        assert!(engine.search_thread.is_none() && !engine.pondering.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn stop_ends_an_infinite_search_with_a_best_move() {
+        let mut engine = Engine::new();
+
+        engine.process_command(UciCommand::Go(Go {
+            infinite: true,
+            ..Default::default()
+        }));
+
+        thread::sleep(std::time::Duration::from_millis(100));
+
+        assert!(
+            engine.stop_search().is_some_and(|mv| mv != ChessMove::NULL_MOVE),
+            "expected stop to end the search with a best move"
+        );
+        assert!(engine.search_thread.is_none());
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    sync::{Arc, Mutex, atomic::AtomicBool},
+    time::Instant,
+};
+
+use chessframe::{board::Board, chess_move::ChessMove};
+
+use crate::{
+    engine::Engine,
+    move_sorter::MoveSorter,
+    options::Options,
+    san::parse_san,
+    search::{Search, SearchLimits},
+    transposition_table::TranspositionTable,
+};
+
+pub struct EpdEntry {
+    pub board: Board,
+    pub id: String,
+    pub best_moves: Vec<ChessMove>,
+    pub avoid_moves: Vec<ChessMove>,
+}
+
+impl EpdEntry {
+    // Four FEN fields followed by `opcode operands;` pairs, the move counters are left out.
+    pub fn parse(line: &str) -> Result<EpdEntry, String> {
+        let fields: Vec<&str> = line.splitn(5, char::is_whitespace).collect();
+        if fields.len() < 4 {
+            return Err("expected the four position fields".to_string());
+        }
+
+        let board = Engine::try_from_fen(&format!("{} 0 1", fields[..4].join(" ")))?;
+
+        let mut entry = EpdEntry {
+            board,
+            id: String::new(),
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+        };
+
+        for operation in fields.get(4).unwrap_or(&"").split(';').map(str::trim) {
+            let Some((opcode, operands)) = operation.split_once(char::is_whitespace) else {
+                continue;
+            };
+
+            match opcode {
+                "id" => entry.id = operands.trim().trim_matches('"').to_string(),
+                "bm" | "am" => {
+                    let mut moves = Vec::new();
+                    for san in operands.split_whitespace() {
+                        moves.push(parse_san(&board, san).ok_or(format!("unexpected move {}", san))?);
+                    }
+
+                    if opcode == "bm" {
+                        entry.best_moves = moves;
+                    } else {
+                        entry.avoid_moves = moves;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if entry.best_moves.is_empty() && entry.avoid_moves.is_empty() {
+            return Err("expected a bm or am operation".to_string());
+        }
+
+        Ok(entry)
+    }
+
+    pub fn solved_by(&self, mv: ChessMove) -> bool {
+        (self.best_moves.is_empty() || self.best_moves.contains(&mv)) && !self.avoid_moves.contains(&mv)
+    }
+}
+
+// Searches every position of the suite to a fixed depth and returns how many were solved.
+pub fn run_suite(epd: &str, depth: u8) -> (usize, usize) {
+    let mut solved = 0;
+    let mut total = 0;
+
+    for line in epd.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let entry = match EpdEntry::parse(line) {
+            Ok(entry) => entry,
+            Err(error) => {
+                println!("skipping {}: {}", line, error);
+                continue;
+            }
+        };
+
+        let mut search = Search::new(
+            entry.board,
+            SearchLimits {
+                depth: Some(depth),
+                ..Default::default()
+            },
+            Vec::new(),
+            Arc::new(TranspositionTable::with_size_mb(16)),
+            Arc::new(Mutex::new(MoveSorter::new())),
+            Arc::new(AtomicBool::new(false)),
+            Options::default(),
+        );
+
+        let best_move = search.start_search();
+        let passed = best_move.is_some_and(|mv| entry.solved_by(mv));
+
+        total += 1;
+        solved += passed as usize;
+
+        let best_move = best_move.map_or("0000".to_string(), |mv| mv.to_string());
+        println!("{} {} {}", if passed { "pass" } else { "fail" }, entry.id, best_move);
+    }
+
+    (solved, total)
+}
+
+pub fn run(path: &str, depth: u8) {
+    let epd = match std::fs::read_to_string(path) {
+        Ok(epd) => epd,
+        Err(error) => {
+            println!("can't read {}: {}", path, error);
+            return;
+        }
+    };
+
+    let timer = Instant::now();
+    let (solved, total) = run_suite(&epd, depth);
+
+    println!("solved {} of {} in {} ms", solved, total, timer.elapsed().as_millis());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trivial_mates_are_solved() {
+        let epd = "\
+            6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - bm Rd8#; id \"mate.1\";
+            r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - bm Qxf7#; id \"mate.2\";";
+
+        assert!(run_suite(epd, 3) == (2, 2), "expected both mates in one to be found");
+    }
+}
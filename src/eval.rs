@@ -1,32 +1,139 @@
+use std::{cell::RefCell, fmt};
+
 use chessframe::{
     bitboard::{BitBoard, EMPTY},
     board::Board,
     color::Color,
     file::File,
-    magic::{FILES, get_adjacent_files, get_bishop_moves, get_knight_moves, get_rook_moves},
+    magic::{FILES, get_adjacent_files, get_bishop_moves, get_king_moves, get_knight_moves, get_rook_moves},
     piece::{PIECES, Piece},
     square::Square,
 };
 
-use crate::piecesquaretable::PieceSquareTable;
+use crate::{
+    pawn_hash_table::{PawnEntry, PawnHashTable},
+    piecesquaretable::PieceSquareTable,
+};
 
 #[inline(always)]
 pub const fn s(mg: i32, eg: i32) -> i32 {
     mg + (eg << 16)
 }
 
+pub const DARK_SQUARES: BitBoard = BitBoard(0xAA55AA55AA55AA55);
+
+// Indexed by the relative rank of the passed pawn.
+pub const PASSED_PAWN_BONUS: [i32; 8] = [
+    s(0, 0), s(5, 10), s(10, 20), s(15, 35), s(30, 60), s(50, 100), s(80, 150), s(0, 0),
+];
+
+// Indexed by the knight's file distance from the edge, central outposts are worth the most.
+pub const OUTPOST_BONUS: [i32; 4] = [s(10, 5), s(15, 8), s(22, 10), s(30, 15)];
+
+// Threats against the side to move's pieces, the other side's loose pieces are quiescence's job.
+pub const THREAT_BY_PAWN: i32 = s(40, 30);
+pub const THREAT_BY_MINOR: i32 = s(30, 25);
+pub const HANGING: i32 = s(25, 20);
+
+// Minor pieces lose some of their worth in the endgame, while pawns and the heavy pieces gain.
 pub const PIECE_VALUES_MG: [i32; 6] = [100, 310, 350, 500, 900, 0];
-pub const PIECE_VALUES_EG: [i32; 6] = [100, 310, 350, 500, 900, 0];
+pub const PIECE_VALUES_EG: [i32; 6] = [120, 290, 330, 550, 950, 0];
+
+// One evaluation term, each side scored from its own point of view.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvalTerm {
+    pub white: i32,
+    pub black: i32,
+}
+
+impl EvalTerm {
+    pub fn net(&self) -> i32 {
+        self.white - self.black
+    }
+}
+
+// Tapered centipawns per term, so the d command can show where a score comes from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvalBreakdown {
+    pub material: EvalTerm,
+    pub piece_square: EvalTerm,
+    pub mobility: EvalTerm,
+    pub pawn_structure: EvalTerm,
+    pub pieces: EvalTerm,
+    pub king_safety: EvalTerm,
+    pub threats: EvalTerm,
+    // Applied from white's point of view like the sum of the terms.
+    pub check: i32,
+    pub tempo: i32,
+    pub half_move_clock: i32,
+    pub side_to_move: Color,
+}
+
+impl EvalBreakdown {
+    pub fn terms(&self) -> [(&'static str, EvalTerm); 7] {
+        [
+            ("Material", self.material),
+            ("Piece square", self.piece_square),
+            ("Mobility", self.mobility),
+            ("Pawn structure", self.pawn_structure),
+            ("Pieces", self.pieces),
+            ("King safety", self.king_safety),
+            ("Threats", self.threats),
+        ]
+    }
+
+    pub fn total(&self) -> i32 {
+        let mut score: i32 = self.terms().iter().map(|(_, term)| term.net()).sum();
+
+        // Drift towards a draw as the fifty move rule comes closer.
+        score = score * (200 - self.half_move_clock.min(100)) / 200;
+
+        score += self.check + self.tempo;
+
+        if self.side_to_move == Color::White {
+            score
+        } else {
+            -score
+        }
+    }
+}
+
+impl fmt::Display for EvalBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>16} | {:>7} | {:>7} | {:>7}", "Term", "White", "Black", "Total")?;
+
+        for (name, term) in self.terms() {
+            writeln!(f, "{:>16} | {:>7} | {:>7} | {:>7}", name, term.white, term.black, term.net())?;
+        }
+
+        writeln!(f, "{:>16} | {:>7} | {:>7} | {:>7}", "Check", "", "", self.check)?;
+        writeln!(f, "{:>16} | {:>7} | {:>7} | {:>7}", "Tempo", "", "", self.tempo)?;
+        write!(f, "{:>16} | {:>7} | {:>7} | {:>7}", "Final", "", "", self.total())
+    }
+}
 
 pub struct Eval<'a> {
     board: &'a Board,
+    pawn_hash_table: Option<RefCell<&'a mut PawnHashTable>>,
 }
 
 impl Eval<'_> {
     pub const MATE_SCORE: i32 = 100_000_000;
+    // The side to move gets to improve its position first.
+    pub const TEMPO: i32 = 10;
 
     pub fn new(board: &Board) -> Eval<'_> {
-        Eval { board }
+        Eval {
+            board,
+            pawn_hash_table: None,
+        }
+    }
+
+    pub fn with_pawn_hash_table<'a>(board: &'a Board, pawn_hash_table: &'a mut PawnHashTable) -> Eval<'a> {
+        Eval {
+            board,
+            pawn_hash_table: Some(RefCell::new(pawn_hash_table)),
+        }
     }
 
     pub fn piece_value(piece: Piece) -> i32 {
@@ -36,58 +143,112 @@ impl Eval<'_> {
         s(mg_score, eg_score)
     }
 
-    pub fn eval(&self) -> i32 {
-        let mut score = 0;
-        let mut mobility_score = 0;
-
-        let game_phase = Self::calculate_game_phase(self.board);
+    pub fn piece_value_phased(piece: Piece, game_phase: i32) -> i32 {
+        Self::taper(Self::piece_value(piece), game_phase)
+    }
 
-        if game_phase > 200 && self.insufficient_material() {
+    pub fn eval(&self) -> i32 {
+        if Self::calculate_game_phase(self.board) > 200 && self.insufficient_material() {
             return 0;
         }
 
-        let mg_score = |score: i32| score as u16 as i16;
-        let eg_score = |score: i32| (((score + 0x8000) as u32) >> 16) as u16 as i16;
-
-        for piece in PIECES {
-            for square in self.board.pieces_color(piece, Color::White) {
-                score +=
-                    Self::piece_value(piece) + PieceSquareTable::read(square, piece, Color::White);
-
-                mobility_score += self.mobility_score(square, piece, Color::White);
-            }
+        self.eval_detailed().total()
+    }
 
-            for square in self.board.pieces_color(piece, Color::Black) {
-                score -=
-                    Self::piece_value(piece) + PieceSquareTable::read(square, piece, Color::Black);
+    pub fn eval_detailed(&self) -> EvalBreakdown {
+        let game_phase = Self::calculate_game_phase(self.board);
+        let pawn_structure = self.cached_pawn_structure();
 
-                mobility_score -= self.mobility_score(square, piece, Color::Black);
-            }
+        EvalBreakdown {
+            material: self.term(|color| {
+                let mut score = 0;
+                for piece in PIECES {
+                    score += self.board.pieces_color(piece, color).count_ones() as i32
+                        * Self::piece_value_phased(piece, game_phase);
+                }
+                score
+            }),
+            piece_square: self.term(|color| {
+                let mut score = 0;
+                for piece in PIECES {
+                    for square in self.board.pieces_color(piece, color) {
+                        score += PieceSquareTable::read(square, piece, color);
+                    }
+                }
+                Self::taper(score, game_phase)
+            }),
+            mobility: self.term(|color| {
+                let mut score = 0;
+                for piece in PIECES {
+                    for square in self.board.pieces_color(piece, color) {
+                        score += self.mobility_score(square, piece, color);
+                    }
+                }
+                Self::taper(score, game_phase)
+            }),
+            pawn_structure: self.term(|color| {
+                let score = if color == Color::White { pawn_structure.white } else { pawn_structure.black };
+                Self::taper(Self::relative(score, color), game_phase)
+            }),
+            pieces: self.term(|color| {
+                let score = self.piece_combination_score(color)
+                    + self.back_ranks_score(color)
+                    + self.rook_file_score(color)
+                    + self.outpost_score(color);
+                Self::taper(Self::relative(score, color), game_phase)
+            }),
+            king_safety: self.term(|color| {
+                Self::taper(Self::relative(self.king_safety_score(color), color), game_phase)
+            }),
+            threats: self.term(|color| Self::taper(Self::relative(self.threats_score(color), color), game_phase)),
+            check: match (self.board.in_check(), self.board.side_to_move) {
+                (false, _) => 0,
+                (true, Color::White) => -50,
+                (true, Color::Black) => 50,
+            },
+            tempo: if self.board.side_to_move == Color::White { Eval::TEMPO } else { -Eval::TEMPO },
+            half_move_clock: self.board.half_move_clock as i32,
+            side_to_move: self.board.side_to_move,
         }
+    }
 
-        score += self.pawn_structure_score(Color::White);
-        score += self.pawn_structure_score(Color::Black);
+    fn cached_pawn_structure(&self) -> PawnEntry {
+        let key = PawnHashTable::pawn_key(self.board);
 
-        score += self.piece_combination_score(Color::White);
-        score += self.piece_combination_score(Color::Black);
+        let mut pawn_hash_table = self.pawn_hash_table.as_ref().map(RefCell::borrow_mut);
 
-        score += mobility_score;
+        if let Some(entry) = pawn_hash_table.as_mut().and_then(|table| table.probe(key)) {
+            return entry;
+        }
 
-        score = (mg_score(score) as i32 * (256 - game_phase) + eg_score(score) as i32 * game_phase) / 256;
+        let entry = PawnEntry {
+            key,
+            white: self.pawn_structure_score(Color::White),
+            black: self.pawn_structure_score(Color::Black),
+        };
 
-        if self.board.in_check() {
-            score -= 50;
+        if let Some(table) = pawn_hash_table.as_mut() {
+            table.store(entry);
         }
 
-        if self.board.side_to_move == Color::White {
-            score
-        } else {
-            -score
+        entry
+    }
+
+    fn term(&self, score: impl Fn(Color) -> i32) -> EvalTerm {
+        EvalTerm {
+            white: score(Color::White),
+            black: score(Color::Black),
         }
     }
 
+    // The term functions score from white's point of view, this turns that into the side's own.
+    fn relative(score: i32, color: Color) -> i32 {
+        if color == Color::White { score } else { -score }
+    }
+
     pub fn pawn_structure_score(&self, color: Color) -> i32 {
         let pawns = self.board.pieces_color(Piece::Pawn, color);
+        let enemy_pawns = self.board.pieces_color(Piece::Pawn, !color);
         let mut score = 0;
 
         for (i, file) in FILES.iter().enumerate() {
@@ -98,14 +259,27 @@ impl Eval<'_> {
                 score -= s(doubles * 20, doubles * 40);
             }
 
-            if pawns & get_adjacent_files(File::from_index(i)) == EMPTY {
-                score -= s(10, 20);
+            if on_file > 0 && pawns & get_adjacent_files(File::from_index(i)) == EMPTY {
+                score -= on_file * s(10, 20);
+            }
+        }
+
+        for square in pawns {
+            if (enemy_pawns & Self::front_span(square, color)).is_zero() {
+                let relative_rank = if color == Color::White {
+                    square.rank().to_index()
+                } else {
+                    7 - square.rank().to_index()
+                };
+
+                score += PASSED_PAWN_BONUS[relative_rank];
             }
         }
 
         if color == Color::White { score } else { -score }
     }
 
+    // The bishop pair is counted once no matter how many bishops, knight pairs are slightly redundant.
     pub fn piece_combination_score(&self, color: Color) -> i32 {
         let mut score = 0;
 
@@ -120,6 +294,163 @@ impl Eval<'_> {
         if color == Color::White { score } else { -score }
     }
 
+    // Rooks and queens on the seventh, rewarded only while they confine the enemy king to the eighth.
+    pub fn back_ranks_score(&self, color: Color) -> i32 {
+        let enemy_king = self.board.pieces_color(Piece::King, !color);
+
+        if (enemy_king & Self::relative_rank(7, color)).is_zero() {
+            return 0;
+        }
+
+        let cut_off_rank = Self::relative_rank(6, color);
+        let rooks = (self.board.pieces_color(Piece::Rook, color) & cut_off_rank).count_ones() as i32;
+        let queens = (self.board.pieces_color(Piece::Queen, color) & cut_off_rank).count_ones() as i32;
+
+        let score = rooks * s(20, 40) + queens * s(10, 20);
+
+        if color == Color::White { score } else { -score }
+    }
+
+    // Rooks want files without their own pawns, and rooks that see each other defend one another.
+    pub fn rook_file_score(&self, color: Color) -> i32 {
+        let rooks = self.board.pieces_color(Piece::Rook, color);
+        let own_pawns = self.board.pieces_color(Piece::Pawn, color);
+        let enemy_pawns = self.board.pieces_color(Piece::Pawn, !color);
+
+        let mut score = 0;
+
+        for square in rooks {
+            let file = FILES[square.file().to_index()];
+
+            if (own_pawns & file).is_zero() {
+                if (enemy_pawns & file).is_zero() {
+                    score += s(25, 10);
+                } else {
+                    score += s(12, 6);
+                }
+            }
+        }
+
+        if rooks.count_ones() >= 2 {
+            let rook = rooks.to_square();
+
+            if (get_rook_moves(rook, self.board.combined()) & rooks).is_not_zero() {
+                score += s(10, 5);
+            }
+        }
+
+        if color == Color::White { score } else { -score }
+    }
+
+    // A knight on ranks 4 to 6, covered by its own pawn, that no enemy pawn can ever chase away.
+    pub fn outpost_score(&self, color: Color) -> i32 {
+        let knights = self.board.pieces_color(Piece::Knight, color);
+        let enemy_pawns = self.board.pieces_color(Piece::Pawn, !color);
+        let defended = self.pawn_attacks(color);
+
+        let mut score = 0;
+
+        for square in knights {
+            let rank = square.rank().to_index();
+            let relative_rank = if color == Color::White { rank } else { 7 - rank };
+            let file = square.file().to_index();
+
+            let attackable = Self::front_span(square, color) & !FILES[file];
+
+            if (3..=5).contains(&relative_rank) && defended.is_set(square) && (enemy_pawns & attackable).is_zero() {
+                score += OUTPOST_BONUS[file.min(7 - file)];
+            }
+        }
+
+        if color == Color::White { score } else { -score }
+    }
+
+    // Pieces attacked by something cheaper, or attacked and not defended at all. Only the side to move
+    // is scored, it can't take back a threat in quiescence the way the opponent captures a loose piece.
+    pub fn threats_score(&self, color: Color) -> i32 {
+        if color != self.board.side_to_move {
+            return 0;
+        }
+
+        let (enemy_minor_attacks, enemy_attacks) = self.attacks(!color);
+        let (_, own_attacks) = self.attacks(color);
+        let enemy_pawn_attacks = self.pawn_attacks(!color);
+
+        let mut score = 0;
+
+        for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+            for square in self.board.pieces_color(piece, color) {
+                if enemy_pawn_attacks.is_set(square) {
+                    score -= THREAT_BY_PAWN;
+                } else if matches!(piece, Piece::Rook | Piece::Queen) && enemy_minor_attacks.is_set(square) {
+                    score -= THREAT_BY_MINOR;
+                }
+
+                if enemy_attacks.is_set(square) && !own_attacks.is_set(square) {
+                    score -= HANGING;
+                }
+            }
+        }
+
+        if color == Color::White { score } else { -score }
+    }
+
+    // Squares the minor pieces of `color` attack, and squares any of its pieces attack.
+    fn attacks(&self, color: Color) -> (BitBoard, BitBoard) {
+        let combined = self.board.combined();
+        let pieces = |piece| self.board.pieces_color(piece, color);
+
+        let mut minor_attacks = EMPTY;
+        for square in pieces(Piece::Knight) {
+            minor_attacks |= get_knight_moves(square);
+        }
+        for square in pieces(Piece::Bishop) {
+            minor_attacks |= get_bishop_moves(square, combined);
+        }
+
+        let mut attacks = minor_attacks | self.pawn_attacks(color);
+        for square in pieces(Piece::Rook) {
+            attacks |= get_rook_moves(square, combined);
+        }
+        for square in pieces(Piece::Queen) {
+            attacks |= get_bishop_moves(square, combined) | get_rook_moves(square, combined);
+        }
+        for square in pieces(Piece::King) {
+            attacks |= get_king_moves(square);
+        }
+
+        (minor_attacks, attacks)
+    }
+
+    // The penalties are (almost) middlegame only, so the taper fades them out in the endgame.
+    pub fn king_safety_score(&self, color: Color) -> i32 {
+        let king_file = self.board.pieces_color(Piece::King, color).to_square().file().to_index();
+
+        let own_pawns = self.board.pieces_color(Piece::Pawn, color);
+        let enemy_pawns = self.board.pieces_color(Piece::Pawn, !color);
+
+        let shield_near = Self::relative_rank(1, color);
+        let shield_far = Self::relative_rank(2, color);
+
+        let mut score = 0;
+
+        for file in &FILES[king_file.saturating_sub(1)..=(king_file + 1).min(7)] {
+            if (own_pawns & file).is_zero() {
+                if (enemy_pawns & file).is_zero() {
+                    score -= s(30, 0);
+                } else {
+                    score -= s(15, 0);
+                }
+            } else if (own_pawns & file & shield_near).is_not_zero() {
+                score += s(15, 0);
+            } else if (own_pawns & file & shield_far).is_not_zero() {
+                score += s(8, 0);
+            }
+        }
+
+        if color == Color::White { score } else { -score }
+    }
+
     pub fn insufficient_material(&self) -> bool {
         if self.board.pieces(Piece::Pawn).count_ones() != 0
             || self.board.pieces(Piece::Rook).count_ones() != 0
@@ -136,9 +467,12 @@ impl Eval<'_> {
             return true;
         }
 
-        false
+        // Bishops that all live on the same square color can never force mate.
+        let bishops = self.board.pieces(Piece::Bishop);
+        knight_count == 0 && ((bishops & DARK_SQUARES).is_zero() || (bishops & !DARK_SQUARES).is_zero())
     }
 
+    // Squares a piece attacks that aren't its own pieces or covered by enemy pawns, a few points each.
     pub fn mobility_score(&self, square: Square, piece: Piece, color: Color) -> i32 {
         if piece == Piece::Pawn || piece == Piece::King {
             return 0;
@@ -166,6 +500,14 @@ impl Eval<'_> {
         }
     }
 
+    pub fn taper(score: i32, game_phase: i32) -> i32 {
+        let mg_score = score as u16 as i16 as i32;
+        let eg_score = (((score + 0x8000) as u32) >> 16) as u16 as i16 as i32;
+
+        (mg_score * (256 - game_phase) + eg_score * game_phase) / 256
+    }
+
+    // 0 with all the pieces on the board, up to 256 when only kings and pawns are left.
     pub fn calculate_game_phase(board: &Board) -> i32 {
         const TOTAL_PHASE: i32 = 24;
 
@@ -184,18 +526,521 @@ impl Eval<'_> {
         score.abs() >= Eval::MATE_SCORE - 1000
     }
 
+    // The squares in front of a pawn on its own and the adjacent files.
+    fn front_span(square: Square, color: Color) -> BitBoard {
+        let file = square.file().to_index();
+        let rank = square.rank().to_index();
+
+        let files = FILES[file] | get_adjacent_files(File::from_index(file));
+        let ahead = if color == Color::White {
+            u64::MAX.checked_shl(8 * (rank as u32 + 1)).unwrap_or(0)
+        } else {
+            (1u64 << (8 * rank)) - 1
+        };
+
+        files & BitBoard(ahead)
+    }
+
+    fn relative_rank(rank: usize, color: Color) -> BitBoard {
+        let rank = if color == Color::White { rank } else { 7 - rank };
+
+        BitBoard(0xFF << (8 * rank))
+    }
+
     fn pawn_attacks(&self, color: Color) -> BitBoard {
         match color {
             Color::White => {
                 ((self.board.pieces_color(Piece::Pawn, color) << 7) & !BitBoard(0x8080808080808080))
                     | ((self.board.pieces_color(Piece::Pawn, color) << 9)
-                        & !BitBoard(0x1010101010101010))
+                        & !BitBoard(0x0101010101010101))
             }
             Color::Black => {
-                ((self.board.pieces_color(Piece::Pawn, color) >> 7) & !BitBoard(0x1010101010101010))
+                ((self.board.pieces_color(Piece::Pawn, color) >> 7) & !BitBoard(0x0101010101010101))
                     | ((self.board.pieces_color(Piece::Pawn, color) >> 9)
                         & !BitBoard(0x8080808080808080))
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Random;
+
+    // The same position with the colors swapped and the board flipped, as seen from the other side.
+    fn mirror(fen: &str) -> String {
+        let fields = fen.split_whitespace().collect::<Vec<&str>>();
+        let swap_case = |c: char| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() };
+
+        let placement = fields[0]
+            .split('/')
+            .rev()
+            .map(|rank| rank.chars().map(swap_case).collect::<String>())
+            .collect::<Vec<String>>()
+            .join("/");
+        let side_to_move = if fields[1] == "w" { "b" } else { "w" };
+
+        let swapped = fields[2].chars().map(swap_case).collect::<String>();
+        let castling = if swapped == "-" {
+            swapped
+        } else {
+            "KQkq".chars().filter(|c| swapped.contains(*c)).collect()
+        };
+
+        let en_passant = match fields[3].as_bytes() {
+            [file, rank] => format!("{}{}", *file as char, if *rank == b'3' { '6' } else { '3' }),
+            _ => fields[3].to_string(),
+        };
+
+        format!("{} {} {} {} {} {}", placement, side_to_move, castling, en_passant, fields[4], fields[5])
+    }
+
+    #[test]
+    fn detailed_terms_match_the_term_functions() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 37 1",
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+        ];
+
+        for fen in fens {
+            let board = Board::from_fen(fen);
+            let eval = Eval::new(&board);
+            let game_phase = Eval::calculate_game_phase(&board);
+            let breakdown = eval.eval_detailed();
+
+            // The term functions score both sides from white's point of view.
+            let net = |white: i32, black: i32| Eval::taper(white, game_phase) + Eval::taper(black, game_phase);
+            let pieces = |color: Color| {
+                eval.piece_combination_score(color)
+                    + eval.back_ranks_score(color)
+                    + eval.rook_file_score(color)
+                    + eval.outpost_score(color)
+            };
+            let material = PIECES
+                .iter()
+                .map(|&piece| {
+                    let count = |color: Color| board.pieces_color(piece, color).count_ones() as i32;
+                    (count(Color::White) - count(Color::Black)) * Eval::piece_value_phased(piece, game_phase)
+                })
+                .sum::<i32>();
+
+            assert!(breakdown.material.net() == material, "expected the material difference in {}", fen);
+            assert!(
+                breakdown.pawn_structure.net()
+                    == net(eval.pawn_structure_score(Color::White), eval.pawn_structure_score(Color::Black)),
+                "expected the pawn structure term to match pawn_structure_score in {}",
+                fen
+            );
+            assert!(
+                breakdown.pieces.net() == net(pieces(Color::White), pieces(Color::Black)),
+                "expected the pieces term to match the piece scores in {}",
+                fen
+            );
+            assert!(
+                breakdown.king_safety.net()
+                    == net(eval.king_safety_score(Color::White), eval.king_safety_score(Color::Black)),
+                "expected the king safety term to match king_safety_score in {}",
+                fen
+            );
+            assert!(
+                breakdown.threats.net() == net(eval.threats_score(Color::White), eval.threats_score(Color::Black)),
+                "expected the threats term to match threats_score in {}",
+                fen
+            );
+            assert!(
+                breakdown.check == if board.in_check() { -50 } else { 0 },
+                "expected the check penalty only where white is in check in {}",
+                fen
+            );
+            assert!(
+                (breakdown.tempo > 0) == (board.side_to_move == Color::White),
+                "expected the tempo to favour the side to move in {}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn start_position_is_balanced() {
+        let board = Board::default();
+
+        assert!(
+            Eval::new(&board).eval().abs() <= 10,
+            "expected the start position to evaluate to roughly 0"
+        );
+    }
+
+    #[test]
+    fn rook_gains_on_a_knight_in_the_endgame() {
+        let opening = Eval::piece_value_phased(Piece::Rook, 0) - Eval::piece_value_phased(Piece::Knight, 0);
+        let endgame = Eval::piece_value_phased(Piece::Rook, 256) - Eval::piece_value_phased(Piece::Knight, 256);
+
+        assert!(opening == 190, "expected the middlegame values at the opening");
+        assert!(endgame > opening, "expected a rook to be worth relatively more in a pawn endgame");
+    }
+
+    #[test]
+    fn centralized_knight_beats_cornered_knight() {
+        // The pawns keep a lone knight from being scored as a dead draw.
+        let centralized = Board::from_fen("4k3/7p/8/4N3/8/8/7P/4K3 w - - 0 1");
+        let cornered = Board::from_fen("4k3/7p/8/8/8/8/7P/N3K3 w - - 0 1");
+
+        assert!(
+            Eval::new(&centralized).eval() - Eval::new(&cornered).eval() >= 50,
+            "expected a knight on e5 to be worth clearly more than a knight on a1"
+        );
+    }
+
+    #[test]
+    fn piece_square_tables_mirror_for_black() {
+        assert!(
+            PieceSquareTable::read(Square::E5, Piece::Knight, Color::White)
+                == PieceSquareTable::read(Square::E4, Piece::Knight, Color::Black),
+            "expected e5 for white to score the same as e4 for black"
+        );
+        assert!(
+            PieceSquareTable::read(Square::G2, Piece::Pawn, Color::White)
+                == PieceSquareTable::read(Square::G7, Piece::Pawn, Color::Black),
+            "expected g2 for white to score the same as g7 for black"
+        );
+    }
+
+    #[test]
+    fn game_phase_spans_opening_to_bare_kings() {
+        let start = Board::default();
+        let bare_kings = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+
+        assert!(
+            Eval::calculate_game_phase(&start) == 0,
+            "expected the start position to be pure middlegame"
+        );
+        assert!(
+            Eval::calculate_game_phase(&bare_kings) == 256,
+            "expected bare kings to be pure endgame"
+        );
+    }
+
+    #[test]
+    fn rook_on_seventh_cutting_off_king_is_rewarded() {
+        let eval = |fen| Eval::new(&Board::from_fen(fen)).eval();
+        let without = eval("6k1/8/8/8/8/8/8/6K1 w - - 0 1");
+
+        let cut_off = eval("6k1/4R3/8/8/8/8/8/6K1 w - - 0 1") - without;
+        let loose = eval("6k1/8/8/4R3/8/8/8/6K1 w - - 0 1") - without;
+
+        assert!(
+            cut_off > loose && loose > 0,
+            "expected the rook to gain more on the seventh with the king on the eighth, got {} and {}",
+            cut_off,
+            loose
+        );
+    }
+
+    #[test]
+    fn rook_on_eighth_with_king_on_seventh_is_neutral() {
+        let board = Board::from_fen("4R3/6k1/8/8/8/8/8/6K1 w - - 0 1");
+
+        assert!(
+            Eval::new(&board).back_ranks_score(Color::White) == 0,
+            "expected no bonus for a rook behind the king, which doesn't confine it"
+        );
+    }
+
+    #[test]
+    fn rook_on_seventh_without_cut_off_king_is_neutral() {
+        let fen = "8/4R3/6k1/8/8/8/8/6K1 w - - 0 1";
+        let board = Board::from_fen(fen);
+
+        assert!(
+            Eval::new(&board).back_ranks_score(Color::White) == 0,
+            "expected no bonus when the enemy king has left its back rank"
+        );
+    }
+
+    #[test]
+    fn rook_prefers_open_over_semi_open_over_closed_files() {
+        let open = Board::from_fen("4k3/pppp1ppp/8/8/8/8/PPPP1PPP/4RK2 w - - 0 1");
+        let semi_open = Board::from_fen("4k3/pppppppp/8/8/8/8/PPPP1PPP/4RK2 w - - 0 1");
+        let closed = Board::from_fen("4k3/pppppppp/8/8/8/8/PPPPPPPP/4RK2 w - - 0 1");
+
+        let score = |board: &Board| Eval::taper(Eval::new(board).rook_file_score(Color::White), 0);
+
+        assert!(score(&open) > score(&semi_open), "expected an open file to beat a semi-open one");
+        assert!(score(&semi_open) > score(&closed), "expected a semi-open file to beat a closed one");
+        assert!(score(&closed) == 0, "expected no bonus behind an own pawn");
+    }
+
+    #[test]
+    fn connected_rooks_are_rewarded() {
+        let connected = Board::from_fen("4k3/8/8/8/8/8/8/R3RK2 w - - 0 1");
+        let blocked = Board::from_fen("4k3/8/8/8/8/8/8/R2NRK2 w - - 0 1");
+
+        let score = |board: &Board| Eval::taper(Eval::new(board).rook_file_score(Color::White), 0);
+
+        assert!(score(&connected) > score(&blocked), "expected rooks that see each other to be rewarded");
+    }
+
+    #[test]
+    fn exposed_king_penalty_fades_in_the_endgame() {
+        let opening = Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPP3/RNBQ1RK1 w kq - 0 1");
+        let endgame = Board::from_fen("4k3/pppppppp/8/8/8/8/PPPPP3/6K1 w - - 0 1");
+
+        let opening_penalty = Eval::taper(
+            Eval::new(&opening).king_safety_score(Color::White),
+            Eval::calculate_game_phase(&opening),
+        );
+        let endgame_penalty = Eval::taper(
+            Eval::new(&endgame).king_safety_score(Color::White),
+            Eval::calculate_game_phase(&endgame),
+        );
+
+        assert!(
+            opening_penalty <= -30 && endgame_penalty > -10,
+            "expected a large king safety penalty in the opening and a small one in the endgame"
+        );
+    }
+
+    #[test]
+    fn evaluation_drifts_towards_a_draw_with_the_halfmove_clock() {
+        let fresh = Board::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 80");
+        let stale = Board::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 90 80");
+
+        let fresh_score = Eval::new(&fresh).eval();
+        let stale_score = Eval::new(&stale).eval();
+
+        assert!(
+            stale_score > 0 && stale_score < fresh_score,
+            "expected the same position to score closer to a draw with a high halfmove clock"
+        );
+    }
+
+    #[test]
+    fn rook_on_an_open_file_is_more_mobile() {
+        let blocked = Board::from_fen("4k3/8/8/8/8/8/P7/R3K3 w - - 0 1");
+        let open = Board::from_fen("4k3/8/8/8/8/8/1P6/R3K3 w - - 0 1");
+
+        let blocked_mobility = Eval::taper(Eval::new(&blocked).mobility_score(Square::A1, Piece::Rook, Color::White), 128);
+        let open_mobility = Eval::taper(Eval::new(&open).mobility_score(Square::A1, Piece::Rook, Color::White), 128);
+
+        assert!(
+            open_mobility > blocked_mobility,
+            "expected opening the file in front of the rook to increase its mobility"
+        );
+    }
+
+    #[test]
+    fn doubled_pawns_are_penalized() {
+        let board = Board::from_fen("4k3/1ppp4/8/8/8/2P5/2PP4/4K3 w - - 0 1");
+
+        assert!(
+            Eval::new(&board).pawn_structure_score(Color::White) == -s(20, 40),
+            "expected exactly one doubled pawn penalty"
+        );
+    }
+
+    #[test]
+    fn isolated_pawns_are_penalized_per_pawn() {
+        let board = Board::from_fen("4k3/pppp4/8/8/8/8/P1P5/4K3 w - - 0 1");
+
+        assert!(
+            Eval::new(&board).pawn_structure_score(Color::White) == -2 * s(10, 20),
+            "expected the a and c pawns to be isolated and no penalty for the empty files"
+        );
+    }
+
+    #[test]
+    fn passed_pawns_are_rewarded_by_rank() {
+        let passed = |fen: &str| {
+            Eval::taper(Eval::new(&Board::from_fen(fen)).pawn_structure_score(Color::White), 128)
+        };
+
+        let far = passed("4k3/8/4P3/8/8/8/8/4K3 w - - 0 1");
+        let near = passed("4k3/8/8/8/8/4P3/8/4K3 w - - 0 1");
+        let blocked = passed("4k3/3p4/4P3/8/8/8/8/4K3 w - - 0 1");
+
+        assert!(far > near, "expected an advanced passed pawn to be worth more");
+        assert!(blocked < far, "expected an enemy pawn on an adjacent file to stop the pawn being passed");
+    }
+
+    #[test]
+    fn keeping_the_bishop_pair_is_rewarded() {
+        let combination = |fen: &str| {
+            let board = Board::from_fen(fen);
+            let eval = Eval::new(&board);
+
+            Eval::taper(
+                eval.piece_combination_score(Color::White) + eval.piece_combination_score(Color::Black),
+                Eval::calculate_game_phase(&board),
+            )
+        };
+
+        let white_pair = combination("1nb1k1n1/8/8/8/8/8/8/1NB1KB2 w - - 0 1");
+        let black_pair = combination("1nb1kb2/8/8/8/8/8/8/1NB1K1N1 w - - 0 1");
+
+        assert!(white_pair > 0, "expected white to profit from keeping the bishop pair");
+        assert!(white_pair == -black_pair, "expected the bonus to flip with the colors");
+    }
+
+    #[test]
+    fn dark_squares_start_on_a1() {
+        assert!(
+            DARK_SQUARES.is_set(Square::A1) && DARK_SQUARES.is_set(Square::H8),
+            "expected both long diagonal corners to be dark"
+        );
+        assert!(!DARK_SQUARES.is_set(Square::H1) && !DARK_SQUARES.is_set(Square::B1));
+    }
+
+    #[test]
+    fn dead_positions_are_insufficient_material() {
+        let fens = [
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4KN2 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4KB2 w - - 0 1",
+            "4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        ];
+
+        for fen in fens {
+            assert!(
+                Eval::new(&Board::from_fen(fen)).insufficient_material(),
+                "expected {} to be a dead draw",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn bishop_pair_is_sufficient_material() {
+        let bishop_pair = Board::from_fen("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1");
+        let opposite_bishops = Board::from_fen("4kb2/8/8/8/8/8/8/3BK3 w - - 0 1");
+
+        assert!(
+            !Eval::new(&bishop_pair).insufficient_material(),
+            "expected two bishops on different colors to be able to mate"
+        );
+        assert!(
+            !Eval::new(&opposite_bishops).insufficient_material(),
+            "expected opposite colored bishops to not be a dead position"
+        );
+    }
+
+    #[test]
+    fn intact_pawn_shield_beats_an_advanced_one() {
+        let king_safety = |fen: &str| {
+            let board = Board::from_fen(fen);
+
+            Eval::taper(Eval::new(&board).king_safety_score(Color::White), 0)
+        };
+
+        let intact = king_safety("6k1/8/8/8/8/8/5PPP/6K1 w - - 0 1");
+        let advanced = king_safety("6k1/8/8/8/5PPP/8/8/6K1 w - - 0 1");
+        let missing = king_safety("6k1/8/8/8/8/8/5P2/6K1 w - - 0 1");
+
+        assert!(intact > advanced, "expected pawns next to the king to shield it best");
+        assert!(advanced > missing, "expected advanced pawns to still beat open files");
+    }
+
+    #[test]
+    fn rook_on_second_cutting_off_king_is_rewarded_for_black() {
+        let fen = "6k1/8/8/8/8/8/4r3/6K1 b - - 0 1";
+        let board = Board::from_fen(fen);
+
+        assert!(
+            Eval::new(&board).back_ranks_score(Color::Black) < 0,
+            "expected a bonus for black from white's perspective to be negative"
+        );
+    }
+
+    #[test]
+    fn pawn_attacks_do_not_wrap_around_the_board() {
+        let white = Board::from_fen("4k3/8/8/8/8/8/P6P/4K3 w - - 0 1");
+        assert!(
+            Eval::new(&white).pawn_attacks(Color::White) == BitBoard(0x0000000000420000),
+            "expected the edge pawns to attack only b3 and g3"
+        );
+
+        let black = Board::from_fen("4k3/p6p/8/8/8/8/8/4K3 w - - 0 1");
+        assert!(
+            Eval::new(&black).pawn_attacks(Color::Black) == BitBoard(0x0000420000000000),
+            "expected the edge pawns to attack only b6 and g6"
+        );
+    }
+
+    #[test]
+    fn knight_outposts_are_rewarded() {
+        let outpost = Board::from_fen("4k3/pp3ppp/3p4/3Np3/4P3/8/PPP2PPP/4K3 w - - 0 1");
+        let passive = Board::from_fen("4k3/pp3ppp/3p4/4p3/4P3/8/PPP2PPP/1N2K3 w - - 0 1");
+        let chased = Board::from_fen("4k3/ppp2ppp/3p4/3Np3/4P3/8/PPP2PPP/4K3 w - - 0 1");
+
+        assert!(Eval::new(&outpost).outpost_score(Color::White) == OUTPOST_BONUS[3], "expected a central outpost");
+        assert!(Eval::new(&passive).outpost_score(Color::White) == 0, "expected nothing for a knight at home");
+        assert!(Eval::new(&chased).outpost_score(Color::White) == 0, "expected nothing when c6 can kick the knight");
+        assert!(
+            Eval::new(&outpost).eval() > Eval::new(&passive).eval(),
+            "expected the knight on d5 to be worth more than on b1"
+        );
+    }
+
+    #[test]
+    fn hanging_rook_is_penalized_for_the_side_to_move() {
+        let hanging = Board::from_fen("4k3/8/8/1b6/8/3R4/8/4K3 w - - 0 1");
+        let safe = Board::from_fen("4k3/8/1b6/8/8/3R4/8/4K3 w - - 0 1");
+
+        assert!(
+            Eval::new(&hanging).threats_score(Color::White) == -(THREAT_BY_MINOR + HANGING),
+            "expected the rook to be threatened by the bishop and undefended"
+        );
+        assert!(Eval::new(&safe).threats_score(Color::White) == 0, "expected no threat on a safe rook");
+        assert!(
+            Eval::new(&hanging).eval() < Eval::new(&safe).eval(),
+            "expected the hanging rook to cost white in the evaluation"
+        );
+
+        let black_to_move = Board::from_fen("4k3/8/8/1b6/8/3R4/8/4K3 b - - 0 1");
+        assert!(
+            Eval::new(&black_to_move).threats_score(Color::White) == 0,
+            "expected the opponent's loose pieces to be left to quiescence"
+        );
+    }
+
+    #[test]
+    fn mirrored_positions_evaluate_the_same() {
+        // Scores are from the side to move's point of view, so the mirror scores the same, not negated.
+        let in_check = "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3";
+        assert!(mirror(&mirror(in_check)) == in_check, "expected mirroring twice to give the position back");
+
+        let mut boards = vec![Board::from_fen(in_check)];
+        for seed in 1..=8 {
+            let mut random = Random::new(seed);
+            let mut board = Board::default();
+
+            for _ in 0..60 {
+                let moves = board
+                    .generate_moves_vec(!EMPTY)
+                    .into_iter()
+                    .filter(|&mv| board.make_move_new(mv).is_ok())
+                    .collect::<Vec<_>>();
+                if moves.is_empty() {
+                    break;
+                }
+
+                board = board.make_move_new(moves[random.below(moves.len())]).unwrap();
+                boards.push(board);
+            }
+        }
+
+        for board in boards {
+            let fen = board.to_fen();
+            let mirrored = Board::from_fen(&mirror(&fen));
+
+            assert!(
+                Eval::new(&board).eval() == Eval::new(&mirrored).eval(),
+                "expected {} to evaluate like its mirror {}",
+                fen,
+                mirror(&fen)
+            );
+        }
+    }
+}
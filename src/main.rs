@@ -1,14 +1,41 @@
 use engine::Engine;
 
+mod bench;
+mod book;
 mod engine;
+mod epd;
 mod eval;
+mod move_picker;
 mod move_sorter;
+mod options;
+mod pawn_hash_table;
+mod perft;
 mod piecesquaretable;
+mod random;
+mod san;
 mod search;
+mod tablebase;
 mod time_management;
 mod transposition_table;
 
 fn main() {
+    if std::env::args().nth(1).as_deref() == Some("bench") {
+        bench::run(bench::BENCH_DEPTH);
+        return;
+    }
+
+    // ferrischess epd <file> [depth]
+    if std::env::args().nth(1).as_deref() == Some("epd") {
+        match std::env::args().nth(2) {
+            Some(path) => {
+                let depth = std::env::args().nth(3).and_then(|depth| depth.parse().ok());
+                epd::run(&path, depth.unwrap_or(bench::BENCH_DEPTH));
+            }
+            None => println!("usage: ferrischess epd <file> [depth]"),
+        }
+        return;
+    }
+
     let mut engine = Engine::new();
     engine.run();
 }
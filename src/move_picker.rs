@@ -0,0 +1,290 @@
+use std::sync::{Arc, Mutex};
+
+use chessframe::{bitboard::BitBoard, board::Board, chess_move::ChessMove};
+
+use crate::move_sorter::MoveSorter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    TtMove,
+    GenerateCaptures,
+    GoodCaptures,
+    Killers,
+    GenerateQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+// Hands out the moves of a node one stage at a time, so a cutoff by the TT move or a capture
+// never pays for generating and ordering the quiets.
+pub struct MovePicker<'a> {
+    board: &'a Board,
+    tt_move: ChessMove,
+    ply: u8,
+    previous_move: ChessMove,
+    move_sorter: Arc<Mutex<MoveSorter>>,
+
+    stage: Stage,
+    moves: Vec<ChessMove>,
+    index: usize,
+    // Captures that lose material by SEE, tried after the quiets.
+    bad_captures: Vec<(i32, ChessMove)>,
+    // The killer and countermove, tried before the quiets and skipped among them.
+    refutations: [ChessMove; 2],
+
+    // Move generations so far, counting the one-square ones that check the TT move and refutations.
+    #[cfg(test)]
+    pub generated: usize,
+    #[cfg(test)]
+    pub scored_quiets: usize,
+}
+
+impl MovePicker<'_> {
+    pub fn new(
+        board: &Board,
+        tt_move: ChessMove,
+        ply: u8,
+        previous_move: ChessMove,
+        move_sorter: Arc<Mutex<MoveSorter>>,
+    ) -> MovePicker<'_> {
+        MovePicker {
+            board,
+            tt_move,
+            ply,
+            previous_move,
+            move_sorter,
+
+            stage: Stage::TtMove,
+            moves: Vec::new(),
+            index: 0,
+            bad_captures: Vec::new(),
+            refutations: [ChessMove::NULL_MOVE; 2],
+
+            #[cfg(test)]
+            generated: 0,
+            #[cfg(test)]
+            scored_quiets: 0,
+        }
+    }
+
+    // TT, killer and countermoves may come from other positions, so they have to be moves of this one.
+    fn is_pseudo_legal(&mut self, mv: ChessMove) -> bool {
+        mv != ChessMove::NULL_MOVE && self.generate_moves(BitBoard(1 << mv.to.to_index())).contains(&mv)
+    }
+
+    fn generate_moves(&mut self, mask: BitBoard) -> Vec<ChessMove> {
+        #[cfg(test)]
+        {
+            self.generated += 1;
+        }
+
+        self.board.generate_moves_vec(mask)
+    }
+
+    fn generate(&mut self, mask: BitBoard) {
+        self.moves = self.generate_moves(mask);
+        self.index = 0;
+    }
+
+    fn is_refutation(&mut self, mv: ChessMove) -> bool {
+        mv != self.tt_move
+            && mv.promotion().is_none()
+            && self.board.get_piece(mv.to).is_none()
+            && self.is_pseudo_legal(mv)
+            && MoveSorter::is_safe_quiet(self.board, mv)
+    }
+
+    fn pick_refutations(&mut self) {
+        let (killer, countermove) = {
+            let move_sorter = self.move_sorter.lock().unwrap();
+            let killer = move_sorter.killer_moves.get(self.ply as usize).copied();
+
+            (killer.unwrap_or(ChessMove::NULL_MOVE), move_sorter.countermove(self.previous_move))
+        };
+
+        if self.is_refutation(killer) {
+            self.refutations[0] = killer;
+        }
+        if countermove != killer && self.is_refutation(countermove) {
+            self.refutations[1] = countermove;
+        }
+        self.index = 0;
+    }
+}
+
+impl Iterator for MovePicker<'_> {
+    type Item = ChessMove;
+
+    fn next(&mut self) -> Option<ChessMove> {
+        loop {
+            match self.stage {
+                Stage::TtMove => {
+                    self.stage = Stage::GenerateCaptures;
+
+                    if self.is_pseudo_legal(self.tt_move) {
+                        return Some(self.tt_move);
+                    }
+                }
+                // MVV-LVA is cheap, SEE is only worked out for the capture about to be tried.
+                Stage::GenerateCaptures => {
+                    self.generate(self.board.occupancy(!self.board.side_to_move));
+                    self.moves.sort_by_key(|&mv| -MoveSorter::mvv_lva(self.board, mv));
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => {
+                    let Some(&mv) = self.moves.get(self.index) else {
+                        self.pick_refutations();
+                        self.stage = Stage::Killers;
+                        continue;
+                    };
+                    self.index += 1;
+
+                    if mv == self.tt_move {
+                        continue;
+                    }
+
+                    let see = MoveSorter::see(self.board, mv);
+                    if see >= 0 {
+                        return Some(mv);
+                    }
+
+                    self.bad_captures.push((see, mv));
+                }
+                Stage::Killers => {
+                    let Some(&mv) = self.refutations.get(self.index) else {
+                        self.stage = Stage::GenerateQuiets;
+                        continue;
+                    };
+                    self.index += 1;
+
+                    if mv != ChessMove::NULL_MOVE {
+                        return Some(mv);
+                    }
+                }
+                // Empty squares, which also covers en passant, castling and quiet promotions.
+                Stage::GenerateQuiets => {
+                    self.generate(!self.board.combined());
+                    self.moves.retain(|mv| *mv != self.tt_move && !self.refutations.contains(mv));
+
+                    #[cfg(test)]
+                    {
+                        self.scored_quiets += self.moves.len();
+                    }
+
+                    self.move_sorter.lock().unwrap().sort_moves(
+                        self.board,
+                        &mut self.moves,
+                        ChessMove::NULL_MOVE,
+                        self.ply,
+                        self.previous_move,
+                    );
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => {
+                    if let Some(&mv) = self.moves.get(self.index) {
+                        self.index += 1;
+                        return Some(mv);
+                    }
+
+                    // Popped from the back, so the least losing capture goes first.
+                    self.bad_captures.sort_by_key(|&(see, _)| see);
+                    self.stage = Stage::BadCaptures;
+                }
+                Stage::BadCaptures => match self.bad_captures.pop() {
+                    Some((_, mv)) => return Some(mv),
+                    None => self.stage = Stage::Done,
+                },
+                Stage::Done => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chessframe::{bitboard::EMPTY, square::Square};
+
+    use super::*;
+
+    fn picker_for(board: &Board, tt_move: ChessMove) -> MovePicker<'_> {
+        let move_sorter = Arc::new(Mutex::new(MoveSorter::new()));
+
+        MovePicker::new(board, tt_move, 0, ChessMove::NULL_MOVE, move_sorter)
+    }
+
+    #[test]
+    fn tt_move_comes_before_the_full_generation() {
+        let board = Board::from_fen("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2");
+        let tt_move = ChessMove::new(Square::G1, Square::F3);
+
+        let mut picker = picker_for(&board, tt_move);
+
+        assert!(picker.next() == Some(tt_move), "expected the TT move first");
+        assert!(picker.generated == 1, "expected only the moves to the TT move's square before it is searched");
+
+        assert!(picker.next() == Some(ChessMove::new(Square::E4, Square::D5)), "expected the capture next");
+        assert!(picker.generated == 2, "expected the captures to be generated and the quiets to stay ungenerated");
+    }
+
+    #[test]
+    fn tt_move_cutoff_scores_no_quiets() {
+        let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let tt_move = ChessMove::new(Square::E2, Square::A6);
+
+        let mut picker = picker_for(&board, tt_move);
+
+        assert!(picker.next() == Some(tt_move), "expected the TT move first");
+        assert!(picker.scored_quiets == 0, "expected a TT move cutoff to leave the quiets unscored");
+
+        picker.by_ref().for_each(drop);
+        assert!(picker.scored_quiets > 0, "expected the quiets to be scored once they are reached");
+    }
+
+    #[test]
+    fn stages_come_in_order() {
+        // Nxe5 wins a pawn, Qxd7+ gives the queen away to the king.
+        let board = Board::from_fen("4k3/3p4/8/4p3/8/5N2/3Q4/4K3 w - - 0 1");
+        let winning = ChessMove::new(Square::F3, Square::E5);
+        let losing = ChessMove::new(Square::D2, Square::D7);
+        let killer = ChessMove::new(Square::E1, Square::F2);
+
+        let move_sorter = Arc::new(Mutex::new(MoveSorter::new()));
+        move_sorter.lock().unwrap().add_killer_move(killer, 0);
+
+        let picked = MovePicker::new(&board, ChessMove::NULL_MOVE, 0, ChessMove::NULL_MOVE, move_sorter)
+            .collect::<Vec<ChessMove>>();
+
+        assert!(picked[0] == winning, "expected the winning capture first");
+        assert!(picked[1] == killer, "expected the killer right after the good captures");
+        assert!(picked.last() == Some(&losing), "expected the losing capture after the quiets");
+    }
+
+    #[test]
+    fn picker_yields_every_move_once() {
+        let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let tt_move = ChessMove::new(Square::E2, Square::A6);
+
+        let mut picked = picker_for(&board, tt_move).collect::<Vec<ChessMove>>();
+        let mut generated = board.generate_moves_vec(!EMPTY);
+
+        assert!(picked[0] == tt_move, "expected the TT move first");
+
+        let key = |mv: &ChessMove| (mv.from.to_index(), mv.to.to_index(), mv.promotion().map(|piece| piece.to_index()));
+        picked.sort_by_key(key);
+        generated.sort_by_key(key);
+
+        assert!(picked == generated, "expected the stages to cover the full move list exactly once");
+    }
+
+    #[test]
+    fn foreign_tt_move_is_skipped() {
+        let board = Board::default();
+        let foreign = ChessMove::new(Square::E4, Square::E5);
+
+        let picked = picker_for(&board, foreign).collect::<Vec<ChessMove>>();
+
+        assert!(!picked.contains(&foreign), "expected a TT move of another position to be dropped");
+        assert!(picked.len() == 20, "expected all twenty moves of the start position");
+    }
+}
@@ -8,9 +8,8 @@ use chessframe::{
     square::Square,
 };
 
-use crate::eval::Eval;
+use crate::eval::PIECE_VALUES_MG;
 
-#[allow(dead_code)]
 pub const MVV_LVA: [i8; 36] = [
     15, 14, 13, 12, 11, 10, // victim Pawn,   attacker P, N, B, R, Q, K
     25, 24, 23, 22, 21, 20, // victim Knight, attacker P, N, B, R, Q, K
@@ -22,36 +21,112 @@ pub const MVV_LVA: [i8; 36] = [
 
 const KILLER_MOVE_COUNT: usize = 12;
 
+// History scores never leave [-MAX_HISTORY, MAX_HISTORY], however often a move is rewarded.
+pub const MAX_HISTORY: i32 = 16_384;
+
 pub struct MoveSorter {
-    pub history: [[i16; 64]; 6],
+    // Quiet move history by side to move, from and to square.
+    pub history: [[[i16; 64]; 64]; 2],
     pub killer_moves: [ChessMove; KILLER_MOVE_COUNT],
+    // The quiet move that last refuted a move, by that move's from and to square.
+    pub countermoves: [[ChessMove; 64]; 64],
+    // History of a quiet move following the previous move, by that move's piece and to square.
+    pub continuation_history: Vec<[[i16; 64]; 6]>,
+    // Only the tests switch it off, to measure how much it helps the ordering.
+    #[cfg(test)]
+    pub use_continuation_history: bool,
 }
 
 impl MoveSorter {
     pub fn new() -> MoveSorter {
         MoveSorter {
-            history: [[0; 64]; 6],
+            history: [[[0; 64]; 64]; 2],
             killer_moves: [ChessMove::NULL_MOVE; KILLER_MOVE_COUNT],
+            countermoves: [[ChessMove::NULL_MOVE; 64]; 64],
+            continuation_history: vec![[[0; 64]; 6]; 6 * 64],
+            #[cfg(test)]
+            use_continuation_history: true,
         }
     }
 
     pub fn clear(&mut self) {
-        self.history = [[0; 64]; 6];
+        self.clear_history();
         self.killer_moves = [ChessMove::NULL_MOVE; KILLER_MOVE_COUNT];
+        self.countermoves = [[ChessMove::NULL_MOVE; 64]; 64];
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history = [[[0; 64]; 64]; 2];
+        self.continuation_history.fill([[0; 64]; 6]);
     }
 
     pub fn age_history(&mut self) {
-        for piece in &mut self.history {
+        for piece in self.history.iter_mut().flatten().chain(self.continuation_history.iter_mut().flatten()) {
             for score in piece {
                 *score /= 2;
             }
         }
     }
 
+    // The previous move's piece already stands on its to square.
+    fn continuation_index(board: &Board, previous_move: ChessMove) -> Option<usize> {
+        if previous_move == ChessMove::NULL_MOVE {
+            return None;
+        }
+
+        let piece = board.get_piece(previous_move.to)?;
+        Some(piece.to_index() * 64 + previous_move.to.to_index())
+    }
+
     #[inline]
-    pub fn update_history(&mut self, to: Square, piece: Piece, value: i16) {
-        let entry = &mut self.history[piece.to_index()][to.to_index()];
-        *entry = (*entry + value).clamp(-20_000, 20_000)
+    pub fn update_continuation_history(
+        &mut self,
+        board: &Board,
+        previous_move: ChessMove,
+        mv: ChessMove,
+        value: i16,
+    ) {
+        let Some(index) = Self::continuation_index(board, previous_move) else {
+            return;
+        };
+
+        let piece = unsafe { board.get_piece(mv.from).unwrap_unchecked() };
+        Self::apply_gravity(&mut self.continuation_history[index][piece.to_index()][mv.to.to_index()], value);
+    }
+
+    #[inline]
+    pub fn update_history(&mut self, color: Color, mv: ChessMove, value: i16) {
+        Self::apply_gravity(&mut self.history[color as usize][mv.from.to_index()][mv.to.to_index()], value);
+    }
+
+    // History gravity: the closer a score is to the bound, the less a bonus of the same sign moves it.
+    fn apply_gravity(entry: &mut i16, bonus: i16) {
+        let bonus = (bonus as i32).clamp(-MAX_HISTORY, MAX_HISTORY);
+        let score = *entry as i32;
+
+        *entry = (score + bonus - score * bonus.abs() / MAX_HISTORY) as i16;
+    }
+
+    // Reward the quiet move that caused the cutoff and punish the quiets tried before it.
+    pub fn update_quiet_histories(
+        &mut self,
+        board: &Board,
+        cutoff: ChessMove,
+        failed: &[ChessMove],
+        depth: u8,
+        malus: i16,
+        previous_move: ChessMove,
+    ) {
+        let bonus = (depth as i16).saturating_mul(depth as i16);
+        let penalty = malus.saturating_mul(depth as i16);
+
+        self.update_history(board.side_to_move, cutoff, bonus);
+        self.update_continuation_history(board, previous_move, cutoff, bonus);
+
+        for &quiet in failed {
+            self.update_history(board.side_to_move, quiet, -penalty);
+            self.update_continuation_history(board, previous_move, quiet, -penalty);
+        }
     }
 
     #[inline]
@@ -61,13 +136,51 @@ impl MoveSorter {
         }
     }
 
-    pub fn sort_moves(&self, board: &Board, moves: &mut [ChessMove], tt_move: ChessMove, ply: u8) {
+    #[inline]
+    pub fn add_countermove(&mut self, previous_move: ChessMove, mv: ChessMove) {
+        if previous_move != ChessMove::NULL_MOVE {
+            self.countermoves[previous_move.from.to_index()][previous_move.to.to_index()] = mv;
+        }
+    }
+
+    pub fn countermove(&self, previous_move: ChessMove) -> ChessMove {
+        if previous_move != ChessMove::NULL_MOVE {
+            self.countermoves[previous_move.from.to_index()][previous_move.to.to_index()]
+        } else {
+            ChessMove::NULL_MOVE
+        }
+    }
+
+    // Most valuable victim first, then least valuable attacker, capturing promotions on top.
+    pub fn mvv_lva(board: &Board, mv: ChessMove) -> i32 {
+        let victim = board.get_piece(mv.to).map_or(0, |piece| piece.to_index());
+        let attacker = unsafe { board.get_piece(mv.from).unwrap_unchecked() };
+
+        let promotion = mv.promotion().map_or(0, |piece| PIECE_VALUES_MG[piece.to_index()]);
+        MVV_LVA[victim * 6 + attacker.to_index()] as i32 + promotion
+    }
+
+    pub fn sort_moves(
+        &self,
+        board: &Board,
+        moves: &mut [ChessMove],
+        tt_move: ChessMove,
+        ply: u8,
+        previous_move: ChessMove,
+    ) {
+        let countermove = self.countermove(previous_move);
+        let continuation =
+            Self::continuation_index(board, previous_move).map(|index| &self.continuation_history[index]);
+        #[cfg(test)]
+        let continuation = continuation.filter(|_| self.use_continuation_history);
+
         let mut scored: Vec<(i32, ChessMove)> = moves
             .iter()
-            .map(|&mv| (self.score_move(board, mv, tt_move, ply), mv))
+            .map(|&mv| (self.score_move(board, mv, tt_move, ply, countermove, continuation), mv))
             .collect();
 
-        scored.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+        // A stable sort keeps equal scores in generation order, so node counts are reproducible.
+        scored.sort_by(|a, b| b.0.cmp(&a.0));
 
         for (i, (_, mv)) in scored.into_iter().enumerate() {
             moves[i] = mv;
@@ -75,31 +188,63 @@ impl MoveSorter {
     }
 
     #[inline]
-    fn score_move(&self, board: &Board, mv: ChessMove, tt_move: ChessMove, ply: u8) -> i32 {
-        if mv == tt_move {
+    fn score_move(
+        &self,
+        board: &Board,
+        mv: ChessMove,
+        tt_move: ChessMove,
+        ply: u8,
+        countermove: ChessMove,
+        continuation: Option<&[[i16; 64]; 6]>,
+    ) -> i32 {
+        let is_quiet = mv.promotion().is_none() && board.get_piece(mv.to).is_none();
+
+        // Don't boost a quiet TT or killer move that just hangs the moved piece.
+        if mv == tt_move && (!is_quiet || Self::is_safe_quiet(board, mv)) {
             return 200_000;
         }
 
         if let Some(promotion) = mv.promotion() {
-            return 40_000 + Eval::piece_value(promotion);
+            return 40_000 + PIECE_VALUES_MG[promotion.to_index()];
         }
 
         let moved = unsafe { board.get_piece(mv.from).unwrap_unchecked() };
         if board.get_piece(mv.to).is_some() {
             let see = Self::see(board, mv);
 
+            // Losing captures go below the quiets, they are rarely better than a quiet move.
             if see >= 0 {
                 return 50_000 + see;
             } else {
-                return 30_000 + see;
+                return -40_000 + see;
             }
         }
 
-        if ply < KILLER_MOVE_COUNT as u8 && self.killer_moves[ply as usize] == mv {
+        if ply < KILLER_MOVE_COUNT as u8
+            && self.killer_moves[ply as usize] == mv
+            && Self::is_safe_quiet(board, mv)
+        {
             return 40_000;
         }
 
-        self.history[moved.to_index()][mv.to.to_index()] as i32
+        if mv == countermove && Self::is_safe_quiet(board, mv) {
+            return 30_000;
+        }
+
+        let mut score = self.history[board.side_to_move as usize][mv.from.to_index()][mv.to.to_index()] as i32;
+        if let Some(continuation) = continuation {
+            score += continuation[moved.to_index()][mv.to.to_index()] as i32;
+        }
+
+        // Walking a piece into a pawn attack is rarely a good idea.
+        let side = board.side_to_move;
+        if moved != Piece::Pawn
+            && (board.pieces_color(Piece::Pawn, !side) & get_pawn_attacks(mv.to, side)).is_not_zero()
+        {
+            score -= 4 * PIECE_VALUES_MG[moved.to_index()];
+        }
+
+        score
     }
 
     pub fn see(board: &Board, mv: ChessMove) -> i32 {
@@ -110,40 +255,81 @@ impl MoveSorter {
             None => return 0,
         };
 
+        // Plain middlegame values, the packed tapered scores would drown out every other ordering term.
+        let value = |piece: Piece| PIECE_VALUES_MG[piece.to_index()];
+        let promotion_rank = matches!(target.rank().to_index(), 0 | 7);
+
         let mut occ = board.combined();
-        let mut side = board.side_to_move;
-        let mut gain = [0; 16];
+        let mut side = !board.side_to_move;
+        let mut gain = [0; 32];
         let mut depth = 0;
 
-        gain[0] = Eval::piece_value(victim);
+        // The first capture is made by the moving piece, not necessarily the cheapest attacker.
+        let mut on_square = unsafe { board.get_piece(mv.from).unwrap_unchecked() };
+        gain[0] = value(victim);
 
-        loop {
+        if let Some(promotion) = mv.promotion() {
+            gain[0] += value(promotion) - value(Piece::Pawn);
+            on_square = promotion;
+        }
+
+        occ.clear_bit(mv.from);
+
+        // Removing each attacker from the occupancy uncovers the sliders behind it.
+        while depth < gain.len() - 1 {
             let attackers = Self::attackers_to(board, target, side, occ);
             if attackers.is_zero() {
                 break;
             }
 
-            let (from_square, attacker_piece) =
+            let (from_square, mut attacker_piece) =
                 Self::least_valuable_attacker(board, side, attackers);
             depth += 1;
 
-            gain[depth] = Eval::piece_value(attacker_piece) - gain[depth - 1];
+            gain[depth] = value(on_square) - gain[depth - 1];
 
+            if attacker_piece == Piece::Pawn && promotion_rank {
+                gain[depth] += value(Piece::Queen) - value(Piece::Pawn);
+                attacker_piece = Piece::Queen;
+            }
+
+            on_square = attacker_piece;
             occ.clear_bit(from_square);
 
             side = !side;
         }
 
-        while depth > 1 {
+        // Either side may stop capturing when going on would lose material.
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
             depth -= 1;
-            if gain[depth - 1] > -gain[depth] {
-                gain[depth - 1] = -gain[depth]
-            }
         }
 
         gain[0]
     }
 
+    // A quiet move is unsafe when the piece lands on a square attacked by a cheaper piece,
+    // or on an attacked square nothing defends.
+    pub fn is_safe_quiet(board: &Board, mv: ChessMove) -> bool {
+        let moved = unsafe { board.get_piece(mv.from).unwrap_unchecked() };
+        let side = board.side_to_move;
+
+        let mut occ = board.combined();
+        occ.clear_bit(mv.from);
+
+        let attackers = Self::attackers_to(board, mv.to, !side, occ);
+        if attackers.is_zero() {
+            return true;
+        }
+
+        let (_, cheapest) = Self::least_valuable_attacker(board, !side, attackers);
+        if PIECE_VALUES_MG[cheapest.to_index()] < PIECE_VALUES_MG[moved.to_index()] {
+            return false;
+        }
+
+        Self::attackers_to(board, mv.to, side, occ).is_not_zero()
+    }
+
     fn attackers_to(board: &Board, square: Square, side: Color, occ: BitBoard) -> BitBoard {
         let mut attackers = BitBoard::default();
 
@@ -179,6 +365,8 @@ impl MoveSorter {
 
 #[cfg(test)]
 mod tests {
+    use chessframe::bitboard::EMPTY;
+
     use super::*;
 
     #[test]
@@ -219,4 +407,226 @@ mod tests {
             "expected SEE to be neutral for a equal pawn capture"
         );
     }
+
+    #[test]
+    fn see_starts_with_the_moving_piece() {
+        let fen = "7k/8/3p4/4p3/3P4/8/8/4QK2 w - - 0 1";
+        let board = Board::from_fen(fen);
+
+        let mv = ChessMove::new(Square::E1, Square::E5);
+
+        assert!(
+            MoveSorter::see(&board, mv) < 0,
+            "expected SEE to be negative for a queen taking a defended pawn"
+        );
+    }
+
+    #[test]
+    fn see_counts_x_ray_attackers() {
+        let fen = "4r2k/8/8/4p3/8/8/4R3/4RK2 w - - 0 1";
+        let board = Board::from_fen(fen);
+
+        let mv = ChessMove::new(Square::E2, Square::E5);
+
+        assert!(
+            MoveSorter::see(&board, mv) == PIECE_VALUES_MG[Piece::Pawn.to_index()],
+            "expected the rook behind to win the exchange on e5"
+        );
+    }
+
+    #[test]
+    fn see_includes_the_promotion() {
+        let fen = "r6k/1P6/8/8/8/8/8/K7 w - - 0 1";
+        let board = Board::from_fen(fen);
+
+        let mv = board.infer_move("b7a8q").unwrap();
+
+        assert!(
+            MoveSorter::see(&board, mv)
+                == PIECE_VALUES_MG[Piece::Rook.to_index()] + PIECE_VALUES_MG[Piece::Queen.to_index()]
+                    - PIECE_VALUES_MG[Piece::Pawn.to_index()],
+            "expected SEE to count the captured rook and the promotion"
+        );
+    }
+
+    #[test]
+    fn losing_capture_is_ordered_below_quiets() {
+        let fen = "7k/8/3p4/4p3/8/8/8/4QK2 w - - 0 1";
+        let board = Board::from_fen(fen);
+
+        let losing = ChessMove::new(Square::E1, Square::E5);
+        let quiet = ChessMove::new(Square::F1, Square::G1);
+
+        let mut moves = board.generate_moves_vec(!EMPTY);
+        MoveSorter::new().sort_moves(&board, &mut moves, ChessMove::NULL_MOVE, 0, ChessMove::NULL_MOVE);
+
+        let losing_index = moves.iter().position(|&mv| mv == losing).unwrap();
+        let quiet_index = moves.iter().position(|&mv| mv == quiet).unwrap();
+
+        assert!(
+            quiet_index < losing_index,
+            "expected a quiet king move before a queen losing itself for a pawn"
+        );
+    }
+
+    #[test]
+    fn tt_move_comes_first_and_killer_beats_other_quiets() {
+        let board = Board::default();
+
+        let tt_move = ChessMove::new(Square::G1, Square::F3);
+        let killer = ChessMove::new(Square::B1, Square::C3);
+
+        let mut move_sorter = MoveSorter::new();
+        move_sorter.add_killer_move(killer, 2);
+
+        let mut moves = board.generate_moves_vec(!EMPTY);
+        move_sorter.sort_moves(&board, &mut moves, tt_move, 2, ChessMove::NULL_MOVE);
+
+        assert!(moves[0] == tt_move, "expected the TT move to be ordered first");
+        assert!(moves[1] == killer, "expected the killer to beat the remaining quiets");
+    }
+
+    #[test]
+    fn repeated_cutoffs_raise_a_quiet_move() {
+        let board = Board::default();
+        let quiet = ChessMove::new(Square::H2, Square::H3);
+
+        let mut move_sorter = MoveSorter::new();
+        for _ in 0..4 {
+            move_sorter.update_quiet_histories(&board, quiet, &[], 6, 2, ChessMove::NULL_MOVE);
+        }
+
+        let mut moves = board.generate_moves_vec(!EMPTY);
+        move_sorter.sort_moves(&board, &mut moves, ChessMove::NULL_MOVE, 2, ChessMove::NULL_MOVE);
+        assert!(moves[0] == quiet, "expected the cutoff move to be ordered first");
+
+        move_sorter.clear_history();
+
+        let mut moves = board.generate_moves_vec(!EMPTY);
+        move_sorter.sort_moves(&board, &mut moves, ChessMove::NULL_MOVE, 2, ChessMove::NULL_MOVE);
+        let index = moves.iter().position(|&mv| mv == quiet).unwrap();
+        assert!(index > 0, "expected clearing the history to forget the cutoff move");
+    }
+
+    #[test]
+    fn countermove_rises_after_the_same_previous_move() {
+        let board = Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2");
+        let previous_move = ChessMove::new(Square::E7, Square::E5);
+        let other_move = ChessMove::new(Square::D7, Square::D6);
+        let countermove = ChessMove::new(Square::H2, Square::H3);
+
+        let mut move_sorter = MoveSorter::new();
+        for _ in 0..4 {
+            move_sorter.add_countermove(previous_move, countermove);
+        }
+
+        let mut moves = board.generate_moves_vec(!EMPTY);
+        move_sorter.sort_moves(&board, &mut moves, ChessMove::NULL_MOVE, 2, previous_move);
+        assert!(moves[0] == countermove, "expected the refutation of e5 to be ordered first");
+
+        let mut moves = board.generate_moves_vec(!EMPTY);
+        move_sorter.sort_moves(&board, &mut moves, ChessMove::NULL_MOVE, 2, other_move);
+        let index = moves.iter().position(|&mv| mv == countermove).unwrap();
+        assert!(index > 0, "expected no boost after a different previous move");
+    }
+
+    #[test]
+    fn continuation_history_depends_on_the_previous_move() {
+        let board = Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2");
+        let previous_move = ChessMove::new(Square::E7, Square::E5);
+        let other_move = ChessMove::new(Square::D7, Square::D6);
+        let follow_up = ChessMove::new(Square::H2, Square::H3);
+
+        let mut move_sorter = MoveSorter::new();
+        for _ in 0..4 {
+            move_sorter.update_continuation_history(&board, previous_move, follow_up, 36);
+        }
+
+        let mut moves = board.generate_moves_vec(!EMPTY);
+        move_sorter.sort_moves(&board, &mut moves, ChessMove::NULL_MOVE, 2, previous_move);
+        assert!(moves[0] == follow_up, "expected the follow-up to be ordered first after e5");
+
+        let moves_after_other = {
+            let board = Board::from_fen("rnbqkbnr/ppp1pppp/3p4/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2");
+            let mut moves = board.generate_moves_vec(!EMPTY);
+            move_sorter.sort_moves(&board, &mut moves, ChessMove::NULL_MOVE, 2, other_move);
+            moves
+        };
+        let index = moves_after_other.iter().position(|&mv| mv == follow_up).unwrap();
+        assert!(index > 0, "expected no boost after a different previous move");
+    }
+
+    #[test]
+    fn hanging_tt_move_is_not_boosted_above_a_safe_capture() {
+        let fen = "4k3/8/8/7p/3p4/8/8/1N2K2R w - - 0 1";
+        let board = Board::from_fen(fen);
+
+        let hanging = ChessMove::new(Square::B1, Square::C3);
+        let capture = ChessMove::new(Square::H1, Square::H5);
+
+        let mut moves = board.generate_moves_vec(!EMPTY);
+        MoveSorter::new().sort_moves(&board, &mut moves, hanging, 0, ChessMove::NULL_MOVE);
+
+        let hanging_index = moves.iter().position(|&mv| mv == hanging).unwrap();
+        let capture_index = moves.iter().position(|&mv| mv == capture).unwrap();
+
+        assert!(
+            capture_index < hanging_index,
+            "expected the safe capture to be ordered before the hanging TT move"
+        );
+    }
+
+    #[test]
+    fn history_malus_ranks_failed_quiets_below_the_cutoff_move() {
+        let board = Board::default();
+        let mut move_sorter = MoveSorter::new();
+
+        let failed = ChessMove::new(Square::A2, Square::A3);
+        let cutoff = ChessMove::new(Square::G1, Square::F3);
+
+        move_sorter.update_quiet_histories(&board, cutoff, &[failed], 4, 2, ChessMove::NULL_MOVE);
+
+        let failed_history = move_sorter.history[0][Square::A2.to_index()][Square::A3.to_index()];
+        let cutoff_history = move_sorter.history[0][Square::G1.to_index()][Square::F3.to_index()];
+
+        assert!(
+            failed_history < 0 && failed_history < cutoff_history,
+            "expected the failed quiet to end up with lower history than the cutoff move"
+        );
+    }
+
+    #[test]
+    fn history_gravity_keeps_scores_bounded() {
+        let board = Board::default();
+        let hammered = ChessMove::new(Square::H2, Square::H3);
+        let failed = ChessMove::new(Square::A2, Square::A3);
+
+        let mut move_sorter = MoveSorter::new();
+        for _ in 0..10_000 {
+            move_sorter.update_quiet_histories(&board, hammered, &[failed], 20, 16, ChessMove::NULL_MOVE);
+        }
+
+        for score in move_sorter.history.iter().flatten().flatten() {
+            assert!((-MAX_HISTORY..=MAX_HISTORY).contains(&(*score as i32)), "expected {} to stay in bounds", score);
+        }
+
+        let mut moves = board.generate_moves_vec(!EMPTY);
+        move_sorter.sort_moves(&board, &mut moves, ChessMove::NULL_MOVE, 2, ChessMove::NULL_MOVE);
+        assert!(moves[0] == hammered, "expected the hammered move to stay ordered first");
+        assert!(moves.last() == Some(&failed), "expected the punished move to stay ordered last");
+    }
+
+    #[test]
+    fn null_move_never_matches_a_legal_move() {
+        let move_sorter = MoveSorter::new();
+
+        for fen in crate::bench::BENCH_FENS {
+            let board = Board::from_fen(fen);
+
+            for mv in board.generate_moves_vec(!EMPTY) {
+                assert!(mv != ChessMove::NULL_MOVE, "expected {} in {} to differ from the null move", mv, fen);
+                assert!(!move_sorter.killer_moves.contains(&mv), "expected empty killer slots to match nothing");
+            }
+        }
+    }
 }
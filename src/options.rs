@@ -0,0 +1,353 @@
+use std::fmt;
+
+pub enum OptionKind {
+    Check { default: bool },
+    Spin { default: i64, min: i64, max: i64 },
+    String { default: &'static str },
+}
+
+pub enum OptionValue<'a> {
+    Check(bool),
+    Spin(i64),
+    String(&'a str),
+}
+
+pub struct UciOption {
+    pub name: &'static str,
+    pub kind: OptionKind,
+}
+
+impl UciOption {
+    pub fn parse<'a>(&self, value: Option<&'a str>) -> Result<OptionValue<'a>, String> {
+        let value = value.unwrap_or_default().trim();
+
+        match self.kind {
+            OptionKind::Check { .. } => match value {
+                "true" => Ok(OptionValue::Check(true)),
+                "false" => Ok(OptionValue::Check(false)),
+                _ => Err(format!(
+                    "Invalid value '{}' for option {}, expected true or false",
+                    value, self.name
+                )),
+            },
+            OptionKind::Spin { min, max, .. } => {
+                let parsed = value.parse::<i64>().map_err(|_| {
+                    format!("Invalid value '{}' for option {}, expected an integer", value, self.name)
+                })?;
+
+                if !(min..=max).contains(&parsed) {
+                    return Err(format!(
+                        "Value {} for option {} is out of range, expected {} to {}",
+                        parsed, self.name, min, max
+                    ));
+                }
+
+                Ok(OptionValue::Spin(parsed))
+            }
+            OptionKind::String { .. } => {
+                Ok(OptionValue::String(if value == "<empty>" { "" } else { value }))
+            }
+        }
+    }
+}
+
+impl fmt::Display for UciOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            OptionKind::Check { default } => {
+                write!(f, "option name {} type check default {}", self.name, default)
+            }
+            OptionKind::Spin { default, min, max } => write!(
+                f,
+                "option name {} type spin default {} min {} max {}",
+                self.name, default, min, max
+            ),
+            OptionKind::String { default } => write!(
+                f,
+                "option name {} type string default {}",
+                self.name,
+                if default.is_empty() { "<empty>" } else { default }
+            ),
+        }
+    }
+}
+
+// Standard UCI options first, then the engine's own settings.
+pub const UCI_OPTIONS: &[UciOption] = &[
+    UciOption {
+        name: "Hash",
+        kind: OptionKind::Spin { default: 16, min: 1, max: 1024 },
+    },
+    UciOption {
+        name: "Threads",
+        kind: OptionKind::Spin { default: 1, min: 1, max: 64 },
+    },
+    UciOption {
+        name: "Ponder",
+        kind: OptionKind::Check { default: false },
+    },
+    UciOption {
+        name: "MultiPV",
+        kind: OptionKind::Spin { default: 1, min: 1, max: 256 },
+    },
+    UciOption {
+        name: "Contempt",
+        kind: OptionKind::Spin { default: 0, min: -100, max: 100 },
+    },
+    UciOption {
+        name: "UCI_LimitStrength",
+        kind: OptionKind::Check { default: false },
+    },
+    UciOption {
+        name: "UCI_Elo",
+        kind: OptionKind::Spin { default: 2800, min: 500, max: 2800 },
+    },
+    UciOption {
+        name: "UCI_AnalyseMode",
+        kind: OptionKind::Check { default: false },
+    },
+    UciOption {
+        name: "OwnBook",
+        kind: OptionKind::Check { default: false },
+    },
+    UciOption {
+        name: "BookFile",
+        kind: OptionKind::String { default: "" },
+    },
+    UciOption {
+        name: "SyzygyPath",
+        kind: OptionKind::String { default: "" },
+    },
+    UciOption {
+        name: "MoveRandomness",
+        kind: OptionKind::Spin { default: 0, min: 0, max: 100 },
+    },
+    UciOption {
+        name: "EvalNoise",
+        kind: OptionKind::Spin { default: 0, min: 0, max: 10 },
+    },
+    UciOption {
+        name: "RandomSeed",
+        kind: OptionKind::Spin { default: 0, min: 0, max: i64::MAX },
+    },
+    UciOption {
+        name: "HistoryMalus",
+        kind: OptionKind::Spin { default: 2, min: 0, max: 16 },
+    },
+    UciOption {
+        name: "AspirationWindow",
+        kind: OptionKind::Spin { default: 16, min: 1, max: 100_000 },
+    },
+    UciOption {
+        name: "AspirationDepth",
+        kind: OptionKind::Spin { default: 5, min: 1, max: 255 },
+    },
+    UciOption {
+        name: "ForcedLine",
+        kind: OptionKind::String { default: "" },
+    },
+    UciOption {
+        name: "DeterministicHash",
+        kind: OptionKind::Check { default: false },
+    },
+    UciOption {
+        name: "DefaultDepth",
+        kind: OptionKind::Spin { default: 7, min: 1, max: 255 },
+    },
+    UciOption {
+        name: "MoveOverhead",
+        kind: OptionKind::Spin { default: 10, min: 0, max: 5000 },
+    },
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    // Transposition table size in megabytes.
+    pub hash: usize,
+    // The GUI may let the engine think on the opponent's time, so bestmove names a move to ponder on.
+    pub ponder: bool,
+    // Number of best root moves to report lines for.
+    pub multi_pv: usize,
+    // Centipawns a draw is worth less than zero to the engine.
+    pub contempt: i32,
+    // Root moves scoring within this many centipawns of the best are picked between at random.
+    pub move_randomness: i32,
+    // Up to this many centipawns of fixed per-move noise on the root scores, for opening variety.
+    pub eval_noise: i32,
+    // A seed of 0 means seeding from the clock on every search.
+    pub random_seed: u64,
+    // Quiets that failed before a cutoff lose `history_malus * depth` history.
+    pub history_malus: i16,
+    // Half width of the first aspiration window, and the depth from which windows are used.
+    pub aspiration_window: i32,
+    pub aspiration_depth: u8,
+    // Moves the search has to follow from the root before it may search freely.
+    pub forced_line: Vec<String>,
+    // Always replace transposition table entries so repeated searches fill it identically.
+    pub deterministic_hash: bool,
+    // Depth searched by a go without any depth, time, node or mate limit.
+    pub default_depth: u8,
+    // Milliseconds taken off every allotment for the lag between the GUI and the engine.
+    pub move_overhead: usize,
+    // Search threads including the main one.
+    pub threads: usize,
+    // Play at roughly `elo` strength instead of full strength.
+    pub limit_strength: bool,
+    pub elo: i32,
+    // Analysing rather than playing, so no handicaps, draw bias or time saving.
+    pub analyse_mode: bool,
+    // Play moves from the Polyglot book at `book_file` while the position is in it.
+    pub own_book: bool,
+    pub book_file: String,
+    // Directories holding Syzygy tables, probed in positions with few enough pieces.
+    pub syzygy_path: String,
+    // Set by the debug command rather than setoption, adds SAN lines for people reading the output.
+    pub debug: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            hash: 16,
+            ponder: false,
+            multi_pv: 1,
+            contempt: 0,
+            move_randomness: 0,
+            eval_noise: 0,
+            random_seed: 0,
+            history_malus: 2,
+            aspiration_window: 16,
+            aspiration_depth: 5,
+            forced_line: Vec::new(),
+            deterministic_hash: false,
+            default_depth: 7,
+            move_overhead: 10,
+            threads: 1,
+            limit_strength: false,
+            elo: 2800,
+            analyse_mode: false,
+            own_book: false,
+            book_file: String::new(),
+            syzygy_path: String::new(),
+            debug: false,
+        }
+    }
+}
+
+impl Options {
+    // Weaker play searches shallower and picks between root moves up to a few pawns worse than the best.
+    pub fn strength_limit(&self) -> Option<(u8, i32)> {
+        const MAX_ELO: i32 = 2800;
+        const MIN_ELO: i32 = 500;
+
+        if !self.limit_strength {
+            return None;
+        }
+
+        let depth = 1 + (self.elo - MIN_ELO) / 200;
+        let margin = (MAX_ELO - self.elo) / 5;
+
+        Some((depth as u8, margin))
+    }
+
+    // Option names are case insensitive, and an invalid value leaves the old one in place.
+    pub fn set(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        let option = UCI_OPTIONS
+            .iter()
+            .find(|option| option.name.eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| format!("Unknown option {}", name))?;
+
+        match (option.name, option.parse(value)?) {
+            ("Hash", OptionValue::Spin(value)) => self.hash = value as usize,
+            ("Ponder", OptionValue::Check(value)) => self.ponder = value,
+            ("MultiPV", OptionValue::Spin(value)) => self.multi_pv = value as usize,
+            ("Contempt", OptionValue::Spin(value)) => self.contempt = value as i32,
+            ("MoveRandomness", OptionValue::Spin(value)) => self.move_randomness = value as i32,
+            ("EvalNoise", OptionValue::Spin(value)) => self.eval_noise = value as i32,
+            ("RandomSeed", OptionValue::Spin(value)) => self.random_seed = value as u64,
+            ("HistoryMalus", OptionValue::Spin(value)) => self.history_malus = value as i16,
+            ("AspirationWindow", OptionValue::Spin(value)) => self.aspiration_window = value as i32,
+            ("AspirationDepth", OptionValue::Spin(value)) => self.aspiration_depth = value as u8,
+            ("ForcedLine", OptionValue::String(value)) => {
+                self.forced_line = value.split_whitespace().map(str::to_string).collect();
+            }
+            ("DeterministicHash", OptionValue::Check(value)) => self.deterministic_hash = value,
+            ("DefaultDepth", OptionValue::Spin(value)) => self.default_depth = value as u8,
+            ("MoveOverhead", OptionValue::Spin(value)) => self.move_overhead = value as usize,
+            ("Threads", OptionValue::Spin(value)) => self.threads = value as usize,
+            ("UCI_LimitStrength", OptionValue::Check(value)) => self.limit_strength = value,
+            ("UCI_Elo", OptionValue::Spin(value)) => self.elo = value as i32,
+            ("UCI_AnalyseMode", OptionValue::Check(value)) => self.analyse_mode = value,
+            ("OwnBook", OptionValue::Check(value)) => self.own_book = value,
+            ("BookFile", OptionValue::String(value)) => self.book_file = value.to_string(),
+            ("SyzygyPath", OptionValue::String(value)) => self.syzygy_path = value.to_string(),
+            _ => unreachable!("option {} is declared but never applied", option.name),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_of_range_value_is_rejected_and_old_value_is_kept() {
+        let mut options = Options::default();
+
+        assert!(options.set("HistoryMalus", Some("4")).is_ok());
+        assert!(
+            options.set("HistoryMalus", Some("1000")).is_err(),
+            "expected an out of range value to be rejected"
+        );
+        assert!(
+            options.history_malus == 4,
+            "expected the previous value to be retained"
+        );
+
+        assert!(options.set("Hash", Some("0")).is_err(), "expected a zero Hash to be rejected");
+        assert!(options.set("Hash", Some("1025")).is_err(), "expected a Hash above the maximum to be rejected");
+        assert!(options.hash == 16, "expected the default Hash to be retained");
+    }
+
+    #[test]
+    fn every_option_is_advertised_once_and_applies_its_default() {
+        for (index, option) in UCI_OPTIONS.iter().enumerate() {
+            assert!(
+                UCI_OPTIONS[..index].iter().all(|other| other.name != option.name),
+                "expected {} to be advertised once",
+                option.name
+            );
+
+            let line = option.to_string();
+            assert!(line.starts_with(&format!("option name {} type ", option.name)));
+
+            let default = line.split(" default ").nth(1).unwrap().split(' ').next().unwrap();
+            let mut options = Options::default();
+
+            assert!(
+                options.set(option.name, Some(default)).is_ok() && options == Options::default(),
+                "expected {} to apply its advertised default",
+                option.name
+            );
+        }
+    }
+
+    #[test]
+    fn option_names_are_case_insensitive() {
+        let mut options = Options::default();
+
+        assert!(options.set("historymalus", Some("3")).is_ok());
+        assert!(options.history_malus == 3);
+    }
+
+    #[test]
+    fn unknown_options_and_malformed_values_are_rejected() {
+        let mut options = Options::default();
+
+        assert!(options.set("NoSuchOption", Some("1")).is_err());
+        assert!(options.set("HistoryMalus", Some("lots")).is_err());
+        assert!(options == Options::default());
+    }
+}
@@ -0,0 +1,97 @@
+use chessframe::{board::Board, color::Color, piece::Piece};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PawnEntry {
+    pub key: u64,
+    pub white: i32,
+    pub black: i32,
+}
+
+// Pawn structure scores by pawn placement, which barely changes between neighbouring nodes.
+pub struct PawnHashTable {
+    entries: Vec<PawnEntry>,
+    pub hits: usize,
+}
+
+impl PawnHashTable {
+    pub fn with_capacity(num_entries: usize) -> PawnHashTable {
+        let size = num_entries.next_power_of_two();
+
+        PawnHashTable {
+            entries: vec![
+                PawnEntry {
+                    key: 0,
+                    white: 0,
+                    black: 0,
+                };
+                size
+            ],
+            hits: 0,
+        }
+    }
+
+    // Both pawn bitboards mixed like SplitMix64, so different structures only collide by chance.
+    pub fn pawn_key(board: &Board) -> u64 {
+        let mix = |mut z: u64| {
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            z ^ (z >> 31)
+        };
+
+        let white = board.pieces_color(Piece::Pawn, Color::White).0;
+        let black = board.pieces_color(Piece::Pawn, Color::Black).0;
+
+        mix(white ^ mix(black.wrapping_add(0x9E3779B97F4A7C15)))
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key as usize) & (self.entries.len() - 1)
+    }
+
+    pub fn probe(&mut self, key: u64) -> Option<PawnEntry> {
+        let entry = self.entries[self.index(key)];
+
+        if entry.key == key {
+            self.hits += 1;
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    pub fn store(&mut self, entry: PawnEntry) {
+        let index = self.index(entry.key);
+        self.entries[index] = entry;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::Eval;
+
+    use super::*;
+
+    #[test]
+    fn identical_pawn_structures_hit_the_cache() {
+        let mut pawn_hash_table = PawnHashTable::with_capacity(1024);
+
+        let first = Board::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        let second = Board::from_fen("rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR w KQkq - 2 3");
+
+        assert!(
+            PawnHashTable::pawn_key(&first) == PawnHashTable::pawn_key(&second),
+            "expected the same pawns to share a key"
+        );
+
+        let first_eval = Eval::with_pawn_hash_table(&first, &mut pawn_hash_table).eval();
+        assert!(pawn_hash_table.hits == 0, "expected a cold table to miss");
+
+        let second_eval = Eval::with_pawn_hash_table(&second, &mut pawn_hash_table).eval();
+        assert!(pawn_hash_table.hits == 1, "expected the second structure to hit the cache");
+
+        assert!(
+            first_eval == Eval::new(&first).eval() && second_eval == Eval::new(&second).eval(),
+            "expected cached pawn scores to leave the evaluation unchanged"
+        );
+    }
+}
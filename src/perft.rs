@@ -0,0 +1,138 @@
+use chessframe::{bitboard::EMPTY, board::Board, chess_move::ChessMove};
+
+pub fn perft(board: &Board, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let mut nodes = 0;
+
+    for mv in board.generate_moves_vec(!EMPTY) {
+        if let Ok(node_board) = board.make_move_new(mv) {
+            nodes += perft(&node_board, depth - 1);
+        }
+    }
+
+    nodes
+}
+
+// Node counts by position and depth, since perft reaches the same subtrees through many move orders.
+pub struct PerftTable {
+    entries: Vec<(u64, u8, u64)>,
+}
+
+impl PerftTable {
+    pub fn with_size_mb(size_mb: usize) -> PerftTable {
+        let num_entries = (size_mb * 1024 * 1024) / std::mem::size_of::<(u64, u8, u64)>();
+
+        PerftTable {
+            entries: vec![(0, 0, 0); num_entries.next_power_of_two()],
+        }
+    }
+
+    fn index(&self, hash: u64) -> usize {
+        (hash as usize) & (self.entries.len() - 1)
+    }
+
+    // Depth 0 is never stored, so it marks an empty slot.
+    pub fn probe(&self, hash: u64, depth: u8) -> Option<u64> {
+        let (entry_hash, entry_depth, nodes) = self.entries[self.index(hash)];
+
+        (entry_hash == hash && entry_depth == depth).then_some(nodes)
+    }
+
+    pub fn store(&mut self, hash: u64, depth: u8, nodes: u64) {
+        let index = self.index(hash);
+        self.entries[index] = (hash, depth, nodes);
+    }
+}
+
+pub fn perft_hashed(board: &Board, depth: u8, table: &mut PerftTable) -> u64 {
+    if depth <= 1 {
+        return perft(board, depth);
+    }
+
+    if let Some(nodes) = table.probe(board.hash(), depth) {
+        return nodes;
+    }
+
+    let mut nodes = 0;
+
+    for mv in board.generate_moves_vec(!EMPTY) {
+        if let Ok(node_board) = board.make_move_new(mv) {
+            nodes += perft_hashed(&node_board, depth - 1, table);
+        }
+    }
+
+    table.store(board.hash(), depth, nodes);
+
+    nodes
+}
+
+// The leaf count below every legal root move, handy for finding which move generates wrongly.
+pub fn divide(board: &Board, depth: u8) -> Vec<(ChessMove, u64)> {
+    let mut counts = Vec::new();
+
+    for mv in board.generate_moves_vec(!EMPTY) {
+        if let Ok(node_board) = board.make_move_new(mv) {
+            counts.push((mv, perft(&node_board, depth.saturating_sub(1))));
+        }
+    }
+
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_position_perft() {
+        let board = Board::default();
+
+        for (depth, expected) in [20, 400, 8902, 197281].into_iter().enumerate() {
+            assert!(
+                perft(&board, depth as u8 + 1) == expected,
+                "expected {} nodes at depth {}",
+                expected,
+                depth + 1
+            );
+        }
+    }
+
+    #[test]
+    fn kiwipete_perft() {
+        let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+
+        for (depth, expected) in [48, 2039, 97862].into_iter().enumerate() {
+            assert!(
+                perft(&board, depth as u8 + 1) == expected,
+                "expected {} nodes at depth {}",
+                expected,
+                depth + 1
+            );
+        }
+    }
+
+    #[test]
+    fn hashed_perft_matches_perft() {
+        let board = Board::default();
+        let mut table = PerftTable::with_size_mb(16);
+
+        let hashed = perft_hashed(&board, 5, &mut table);
+
+        assert!(hashed == perft(&board, 5), "expected the table to leave the node count unchanged");
+        assert!(hashed == 4_865_609, "expected {} nodes at depth 5", 4_865_609);
+        assert!(perft_hashed(&board, 5, &mut table) == hashed, "expected a warm table to give the same count");
+    }
+
+    #[test]
+    fn divide_sums_to_perft() {
+        let board = Board::default();
+
+        let counts = divide(&board, 3);
+
+        assert!(counts.len() == 20);
+        assert!(counts.iter().map(|(_, nodes)| nodes).sum::<u64>() == perft(&board, 3));
+    }
+}
@@ -0,0 +1,245 @@
+use chessframe::{
+    bitboard::EMPTY, board::Board, chess_move::ChessMove, file::File, piece::Piece, rank::Rank, square::Square,
+};
+
+fn legal_moves(board: &Board) -> Vec<ChessMove> {
+    board
+        .generate_moves_vec(!EMPTY)
+        .into_iter()
+        .filter(|&mv| board.make_move_new(mv).is_ok())
+        .collect()
+}
+
+fn piece_from_char(c: char) -> Option<Piece> {
+    match c {
+        'N' => Some(Piece::Knight),
+        'B' => Some(Piece::Bishop),
+        'R' => Some(Piece::Rook),
+        'Q' => Some(Piece::Queen),
+        'K' => Some(Piece::King),
+        _ => None,
+    }
+}
+
+// Matches a SAN move against the legal moves, falling back to coordinate notation.
+pub fn parse_san(board: &Board, san: &str) -> Option<ChessMove> {
+    let legal = legal_moves(board);
+
+    let san = san.trim_end_matches(['+', '#', '!', '?']);
+
+    let castling = match san {
+        "O-O" | "0-0" => Some(2),
+        "O-O-O" | "0-0-0" => Some(-2),
+        _ => None,
+    };
+    if let Some(direction) = castling {
+        return legal.into_iter().find(|mv| {
+            board.get_piece(mv.from) == Some(Piece::King)
+                && mv.to.file().to_index() as i32 - mv.from.file().to_index() as i32 == direction
+        });
+    }
+
+    let (body, promotion) = match san.split_once('=') {
+        Some((body, promotion)) => (body, Some(piece_from_char(promotion.chars().next()?)?)),
+        None => (san, None),
+    };
+
+    let mut chars: Vec<char> = body.chars().filter(|&c| c != 'x').collect();
+    let piece = match chars.first().copied().and_then(piece_from_char) {
+        Some(piece) => {
+            chars.remove(0);
+            piece
+        }
+        None => Piece::Pawn,
+    };
+
+    if chars.len() < 2 {
+        return None;
+    }
+
+    let square = |file: char, rank: char| {
+        let file = (file as u8).checked_sub(b'a').filter(|file| *file < 8)?;
+        let rank = (rank as u8).checked_sub(b'1').filter(|rank| *rank < 8)?;
+
+        Some(Square::make_square(Rank::from_index(rank as usize), File::from_index(file as usize)))
+    };
+
+    let to = square(chars[chars.len() - 2], chars[chars.len() - 1]);
+    let hints = &chars[..chars.len() - 2];
+
+    let mut candidates = legal.iter().copied().filter(|mv| {
+        Some(mv.to) == to
+            && board.get_piece(mv.from) == Some(piece)
+            && mv.promotion() == promotion
+            && hints.iter().all(|&hint| match hint {
+                'a'..='h' => mv.from.file().to_index() == (hint as u8 - b'a') as usize,
+                '1'..='8' => mv.from.rank().to_index() == (hint as u8 - b'1') as usize,
+                _ => false,
+            })
+    });
+
+    match (candidates.next(), candidates.next()) {
+        (Some(mv), None) => Some(mv),
+        (Some(_), Some(_)) => None,
+        _ => board.infer_move(san).ok().filter(|mv| legal.contains(mv)),
+    }
+}
+
+fn piece_char(piece: Piece) -> char {
+    match piece {
+        Piece::Pawn => 'P',
+        Piece::Knight => 'N',
+        Piece::Bishop => 'B',
+        Piece::Rook => 'R',
+        Piece::Queen => 'Q',
+        Piece::King => 'K',
+    }
+}
+
+fn square_name(square: Square) -> String {
+    let file = (b'a' + square.file().to_index() as u8) as char;
+    let rank = (b'1' + square.rank().to_index() as u8) as char;
+
+    format!("{}{}", file, rank)
+}
+
+// Standard algebraic notation for a legal move of `board`, for people reading analysis, not for UCI.
+pub fn to_san(board: &Board, mv: ChessMove) -> String {
+    let piece = unsafe { board.get_piece(mv.from).unwrap_unchecked() };
+    let file_change = mv.from.file().to_index().abs_diff(mv.to.file().to_index());
+
+    let mut san = if piece == Piece::King && file_change == 2 {
+        if mv.to.file().to_index() > mv.from.file().to_index() {
+            "O-O".to_string()
+        } else {
+            "O-O-O".to_string()
+        }
+    } else {
+        // En passant is the one capture landing on an empty square.
+        let capture = board.get_piece(mv.to).is_some() || (piece == Piece::Pawn && file_change == 1);
+        let mut san = String::new();
+
+        if piece == Piece::Pawn {
+            if capture {
+                san.push(square_name(mv.from).remove(0));
+            }
+        } else {
+            san.push(piece_char(piece));
+
+            let rivals: Vec<ChessMove> = legal_moves(board)
+                .into_iter()
+                .filter(|other| other.to == mv.to && other.from != mv.from && board.get_piece(other.from) == Some(piece))
+                .collect();
+
+            // The file if that tells the pieces apart, otherwise the rank, otherwise both.
+            if !rivals.is_empty() {
+                let from = square_name(mv.from);
+
+                if rivals.iter().all(|other| other.from.file() != mv.from.file()) {
+                    san.push_str(&from[..1]);
+                } else if rivals.iter().all(|other| other.from.rank() != mv.from.rank()) {
+                    san.push_str(&from[1..]);
+                } else {
+                    san.push_str(&from);
+                }
+            }
+        }
+
+        if capture {
+            san.push('x');
+        }
+        san.push_str(&square_name(mv.to));
+
+        if let Some(promotion) = mv.promotion() {
+            san.push('=');
+            san.push(piece_char(promotion));
+        }
+
+        san
+    };
+
+    if let Some(after) = board.make_move_new(mv).ok().filter(|after| after.in_check()) {
+        san.push(if legal_moves(&after).is_empty() { '#' } else { '+' });
+    }
+
+    san
+}
+
+// A line of moves played one after another from `board`.
+pub fn line_to_san(board: &Board, line: &[ChessMove]) -> String {
+    let mut board = *board;
+    let mut moves = Vec::with_capacity(line.len());
+
+    for &mv in line {
+        if mv == ChessMove::NULL_MOVE {
+            break;
+        }
+
+        moves.push(to_san(&board, mv));
+        match board.make_move_new(mv) {
+            Ok(next) => board = next,
+            Err(_) => break,
+        }
+    }
+
+    moves.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn san_moves_are_matched_to_legal_moves() {
+        let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+
+        assert!(parse_san(&board, "O-O") == Some(ChessMove::new(Square::E1, Square::G1)));
+        assert!(parse_san(&board, "Bxa6") == Some(ChessMove::new(Square::E2, Square::A6)));
+        assert!(parse_san(&board, "dxe6") == Some(ChessMove::new(Square::D5, Square::E6)));
+        assert!(parse_san(&board, "Nb5") == Some(ChessMove::new(Square::C3, Square::B5)));
+        assert!(parse_san(&board, "Qxf6+") == Some(ChessMove::new(Square::F3, Square::F6)));
+        assert!(parse_san(&board, "Ke3").is_none(), "expected an illegal move to be rejected");
+    }
+
+    #[test]
+    fn ambiguous_moves_are_disambiguated() {
+        let knights = Board::from_fen("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1");
+        assert!(to_san(&knights, ChessMove::new(Square::B1, Square::D2)) == "Nbd2");
+
+        let rooks = Board::from_fen("R7/7k/8/8/8/8/8/R3K3 w - - 0 1");
+        assert!(to_san(&rooks, ChessMove::new(Square::A1, Square::A4)) == "R1a4");
+
+        let lone = Board::default();
+        assert!(to_san(&lone, ChessMove::new(Square::G1, Square::F3)) == "Nf3");
+    }
+
+    #[test]
+    fn castling_and_captures_are_written_out() {
+        let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+
+        assert!(to_san(&board, ChessMove::new(Square::E1, Square::G1)) == "O-O");
+        assert!(to_san(&board, ChessMove::new(Square::E1, Square::C1)) == "O-O-O");
+        assert!(to_san(&board, ChessMove::new(Square::D5, Square::E6)) == "dxe6");
+        assert!(to_san(&board, ChessMove::new(Square::E2, Square::A6)) == "Bxa6");
+    }
+
+    #[test]
+    fn checks_and_mates_get_their_suffix() {
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1");
+
+        assert!(to_san(&board, ChessMove::new(Square::D1, Square::D8)) == "Rd8#");
+        assert!(to_san(&board, ChessMove::new(Square::D1, Square::D7)) == "Rd7");
+
+        let check = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        assert!(to_san(&check, ChessMove::new(Square::A1, Square::A8)) == "Ra8+");
+    }
+
+    #[test]
+    fn san_round_trips() {
+        let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+
+        for mv in legal_moves(&board) {
+            assert!(parse_san(&board, &to_san(&board, mv)) == Some(mv), "expected {} to round trip", mv);
+        }
+    }
+}
@@ -1,14 +1,14 @@
 use std::{
-    collections::HashSet,
     sync::{
         Arc, LazyLock, Mutex,
-        atomic::AtomicBool,
+        atomic::{AtomicBool, Ordering},
     },
-    time::Instant,
+    thread,
+    time::{Duration, Instant},
 };
 
 use chessframe::{
-    bitboard::EMPTY,
+    bitboard::{BitBoard, EMPTY},
     board::Board,
     chess_move::ChessMove,
     piece::Piece,
@@ -17,7 +17,13 @@ use chessframe::{
 
 use crate::{
     eval::{Eval, PIECE_VALUES_EG},
+    move_picker::MovePicker,
     move_sorter::MoveSorter,
+    options::Options,
+    pawn_hash_table::PawnHashTable,
+    random::Random,
+    san,
+    tablebase::{TB_WIN_SCORE, Tablebases, Wdl},
     time_management::TimeManagement,
     transposition_table::{Bound, TranspositionTable},
 };
@@ -39,6 +45,11 @@ pub static REDUCTIONS: LazyLock<[[u8; 32]; 32]> = LazyLock::new(|| {
     reductions
 });
 
+const EVAL_CACHE_SIZE: usize = 1 << 16;
+const PAWN_HASH_SIZE: usize = 1 << 14;
+// One more than the deepest ply, so the row below the last ply is always there to copy from.
+const PV_TABLE_SIZE: usize = Search::MAX_PLY as usize + 2;
+
 pub struct SearchInfo {
     pub depth: usize,
     pub seldepth: usize,
@@ -50,16 +61,31 @@ pub struct SearchInfo {
     pub evaluation: isize,
     pub _best_move: ChessMove,
     pub pv: Vec<ChessMove>,
+
+    pub multipv: Option<usize>,
+    pub hashfull: usize,
+
+    // Lower or Upper while an aspiration window has failed and the score is only a bound.
+    pub bound: Bound,
 }
 
 impl SearchInfo {
     pub fn print(&self) {
-        let score = if Eval::mate_score(self.evaluation as i32) {
-            let moves_to_mate = Eval::MATE_SCORE - self.evaluation.abs() as i32;
-            let mate_in_moves = (moves_to_mate + 1) / 2;
+        println!("{}", self.to_info());
+    }
 
+    // Mate scores count plies from the root, UCI wants full moves, negative when getting mated.
+    pub fn mate_in_moves(score: i32) -> isize {
+        let plies_to_mate = Eval::MATE_SCORE - score.abs();
+        let moves_to_mate = (plies_to_mate + 1) / 2;
+
+        score.signum() as isize * moves_to_mate as isize
+    }
+
+    pub fn to_info(&self) -> Info {
+        let mut score = if Eval::mate_score(self.evaluation as i32) {
             Score {
-                mate: Some(self.evaluation.signum() * mate_in_moves as isize),
+                mate: Some(Self::mate_in_moves(self.evaluation as i32)),
                 ..Default::default()
             }
         } else {
@@ -68,6 +94,8 @@ impl SearchInfo {
                 ..Default::default()
             }
         };
+        score.lowerbound = self.bound == Bound::Lower;
+        score.upperbound = self.bound == Bound::Upper;
 
         let pv = self
             .pv
@@ -76,26 +104,61 @@ impl SearchInfo {
             .collect::<Vec<String>>()
             .join(" ");
 
-        let info = Info {
+        Info {
             depth: Some(self.depth),
             seldepth: Some(self.seldepth),
+            multipv: self.multipv,
             pv: Some(pv),
             score: Some(score),
             time: Some(self.time),
             nodes: Some(self.nodes),
             nps: Some(self.nps),
+            hashfull: Some(self.hashfull),
             ..Default::default()
-        };
+        }
+    }
+}
+
+// What the GUI asked for in its go command.
+#[derive(Debug, Clone, Default)]
+pub struct SearchLimits {
+    pub depth: Option<u8>,
+    pub time_management: TimeManagement,
+    // Keep the bestmove back until the GUI sends stop, even when the search is done early.
+    pub infinite: bool,
+    // Set while searching on the opponent's time, ponderhit clears it and starts the clock.
+    pub pondering: Arc<AtomicBool>,
+    // Only these root moves are searched when any are given, illegal ones are ignored.
+    pub search_moves: Vec<String>,
+    // Look for a mate in at most this many moves and stop as soon as one is proven.
+    pub mate: Option<u8>,
+    // Stop once this many nodes have been searched.
+    pub nodes: Option<usize>,
+}
 
-        println!("{}", info);
+impl SearchLimits {
+    // Only the main thread watches the clock, it may extend the allotment and stops the helpers when done.
+    pub fn for_helper(&self) -> SearchLimits {
+        SearchLimits {
+            time_management: TimeManagement::None,
+            ..self.clone()
+        }
     }
 }
 
 pub struct Search {
     board: Board,
     search_depth: u8,
-
-    repetition_table: HashSet<u64>,
+    root_depth: u8,
+    mate: Option<u8>,
+    max_nodes: Option<usize>,
+    infinite: bool,
+    pondering: Arc<AtomicBool>,
+    was_pondering: bool,
+
+    // Hashes of the game history followed by the hashes along the current search path.
+    repetition_table: Vec<u64>,
+    game_history: usize,
     transposition_table: Arc<TranspositionTable>,
     move_sorter: Arc<Mutex<MoveSorter>>,
 
@@ -105,31 +168,150 @@ pub struct Search {
     evaluation_iteration: i32,
     pv_iteration: Vec<ChessMove>,
 
+    root_scores: Vec<(ChessMove, i32)>,
+    root_scores_iteration: Vec<(ChessMove, i32)>,
+
+    // The best lines of the last finished depth, only filled with MultiPV.
+    lines: Vec<(i32, Vec<ChessMove>)>,
+    excluded_root_moves: Vec<ChessMove>,
+    search_moves: Vec<ChessMove>,
+    // The move left out of each ply while testing the TT move for singularity.
+    excluded_moves: Vec<ChessMove>,
+    // The move played at each ply of the current line, a null move for null move pruning.
+    move_stack: Vec<ChessMove>,
+    // Triangular PV table, row `ply` holds the best line found from that ply onwards.
+    pv_table: Vec<[ChessMove; PV_TABLE_SIZE]>,
+    pv_length: Vec<usize>,
+
+    forced_line: Vec<ChessMove>,
+
+    // Static evaluations by key, so transpositions in quiescence don't evaluate twice.
+    eval_cache: Vec<(u64, i32)>,
+    pawn_hash_table: PawnHashTable,
+
+    options: Options,
+    random: Random,
+    // Mixed into every root move's noise, so each search gets its own but keeps it for all iterations.
+    noise_seed: u64,
+
+    // Every info line printed so far.
+    #[cfg(test)]
+    pub reported: Vec<String>,
+
     pub nodes: usize,
     pub seldepth: u8,
+    pub max_qsearch_ply: u8,
+    pub aspiration_researches: usize,
+    pub pvs_researches: usize,
+    pub singular_extensions: usize,
+    pub razor_cutoffs: usize,
+    // Losing captures skipped in quiescence.
+    pub see_prunes: usize,
+    // Beta cutoffs in the main search, and how many of them came from the first move searched.
+    pub cutoffs: usize,
+    pub first_move_cutoffs: usize,
+    pub eval_cache_hits: usize,
 
     pub think_timer: Instant,
     pub time_management: TimeManagement,
 
     pub cancelled: Arc<AtomicBool>,
+
+    // 0 for the main thread, which is the only one that talks to the GUI.
+    pub thread_id: usize,
+
+    pub tablebases: Option<Arc<Tablebases>>,
+    pub tablebase_hits: usize,
+
+    // Knobs the tests turn to measure what each part of the search saves, play always uses the defaults.
+    #[cfg(test)]
+    pub aspiration_failures: u8,
+    #[cfg(test)]
+    pub delta_pruning: bool,
+    #[cfg(test)]
+    pub pvs: bool,
+    #[cfg(test)]
+    pub see_pruning: bool,
 }
 
 impl Search {
     pub const MAX_PLY: u8 = 255;
+    pub const ASPIRATION_FAILURES: u8 = 4;
+    pub const IID_DEPTH: u8 = 6;
+    pub const SINGULAR_DEPTH: u8 = 8;
+    pub const MAX_QSEARCH_PLY: u8 = 12;
 
     pub fn new(
         board: Board,
-        depth: Option<u8>,
-        time_management: TimeManagement,
-        repetition_table: HashSet<u64>,
+        limits: SearchLimits,
+        repetition_table: Vec<u64>,
         transposition_table: Arc<TranspositionTable>,
         move_sorter: Arc<Mutex<MoveSorter>>,
         cancelled: Arc<AtomicBool>,
+        mut options: Options,
     ) -> Search {
+        let mut max_depth = Search::MAX_PLY;
+
+        if options.analyse_mode {
+            options.limit_strength = false;
+            options.contempt = 0;
+            options.move_randomness = 0;
+            options.eval_noise = 0;
+        }
+
+        if let Some((depth, margin)) = options.strength_limit() {
+            max_depth = depth;
+            options.move_randomness = options.move_randomness.max(margin);
+        }
+
+        let mut random = if options.random_seed != 0 {
+            Random::new(options.random_seed)
+        } else {
+            Random::from_time()
+        };
+        let noise_seed = random.next_u64();
+
+        let mut forced_line = Vec::new();
+        let mut line_board = board;
+
+        for mv in &options.forced_line {
+            let Ok(mv) = line_board.infer_move(mv) else {
+                break;
+            };
+            let Ok(next_board) = line_board.make_move_new(mv) else {
+                break;
+            };
+
+            forced_line.push(mv);
+            line_board = next_board;
+        }
+
+        let mate = limits.mate.filter(|&mate| mate > 0);
+
+        let pseudo_legal = board.generate_moves_vec(!EMPTY);
+        let search_moves = limits
+            .search_moves
+            .iter()
+            .filter_map(|mv| board.infer_move(mv).ok())
+            .filter(|&mv| pseudo_legal.contains(&mv) && board.make_move_new(mv).is_ok())
+            .collect();
+
         Search {
             board,
-            search_depth: depth.unwrap_or(Search::MAX_PLY),
-
+            // A mate in n takes 2n - 1 plies, there is no point in searching any deeper than that.
+            search_depth: limits
+                .depth
+                .or(mate.map(|mate| (2 * mate as u16 - 1).min(Search::MAX_PLY as u16) as u8))
+                .unwrap_or(Search::MAX_PLY)
+                .min(max_depth),
+            root_depth: 0,
+            mate,
+            max_nodes: limits.nodes,
+            infinite: limits.infinite,
+            was_pondering: limits.pondering.load(Ordering::Relaxed),
+            pondering: limits.pondering,
+
+            game_history: repetition_table.len(),
             repetition_table,
             transposition_table,
             move_sorter,
@@ -138,26 +320,109 @@ impl Search {
             pv: Vec::new(),
 
             evaluation_iteration: 1234567890,
-            pv_iteration: Vec::new(),
+            // A root without legal moves never writes a line, so it reads as a search without a move.
+            pv_iteration: vec![ChessMove::NULL_MOVE],
+
+            root_scores: Vec::new(),
+            root_scores_iteration: Vec::new(),
+
+            lines: Vec::new(),
+            excluded_root_moves: Vec::new(),
+            search_moves,
+            excluded_moves: vec![ChessMove::NULL_MOVE; Search::MAX_PLY as usize + 1],
+            move_stack: vec![ChessMove::NULL_MOVE; Search::MAX_PLY as usize + 1],
+            pv_table: vec![[ChessMove::NULL_MOVE; PV_TABLE_SIZE]; PV_TABLE_SIZE],
+            pv_length: vec![0; PV_TABLE_SIZE],
+
+            forced_line,
+
+            eval_cache: vec![(0, 0); EVAL_CACHE_SIZE],
+            pawn_hash_table: PawnHashTable::with_capacity(PAWN_HASH_SIZE),
+
+            options,
+            random,
+            noise_seed,
+
+            #[cfg(test)]
+            reported: Vec::new(),
 
             nodes: 0,
             seldepth: 0,
+            max_qsearch_ply: 0,
+            aspiration_researches: 0,
+            pvs_researches: 0,
+            singular_extensions: 0,
+            razor_cutoffs: 0,
+            see_prunes: 0,
+            cutoffs: 0,
+            first_move_cutoffs: 0,
+            eval_cache_hits: 0,
 
             think_timer: Instant::now(),
-            time_management,
+            time_management: limits.time_management,
 
             cancelled,
+
+            thread_id: 0,
+
+            tablebases: None,
+            tablebase_hits: 0,
+
+            #[cfg(test)]
+            aspiration_failures: Search::ASPIRATION_FAILURES,
+            #[cfg(test)]
+            delta_pruning: true,
+            #[cfg(test)]
+            pvs: true,
+            #[cfg(test)]
+            see_pruning: true,
         }
     }
 
-    pub fn start_search(&mut self) {
+    // None only when there is no legal move to play.
+    pub fn start_search(&mut self) -> Option<ChessMove> {
         let mut evaluation = 0;
 
+        // Only the tablebase move is sure to win before the fifty move rule, the search just fills in the line.
+        if self.search_moves.is_empty()
+            && self.forced_line.is_empty()
+            && let Some(best_move) = self.tablebases.as_ref().and_then(|tablebases| tablebases.best_move(&self.board))
+        {
+            self.search_moves = vec![best_move];
+        }
+
+        let root_moves = if self.search_moves.is_empty() {
+            Self::legal_move_count(&self.board)
+        } else {
+            self.search_moves.len()
+        };
+
+        let multi_pv = if self.forced_move(0).is_some() {
+            1
+        } else {
+            self.options.multi_pv.min(root_moves).max(1)
+        };
+
+        // Half the helpers start one ply deeper, so the threads don't all search the same depth at once.
+        let first_depth = (1 + (self.thread_id % 2) as u8).min(self.search_depth);
+
+        let mut stable_iterations = 0;
+        let mut previous_best_move = ChessMove::NULL_MOVE;
+
         self.think_timer = Instant::now();
-        for depth in 1..=self.search_depth {
-            let mut delta = 16;
+        for depth in first_depth..=self.search_depth {
+            self.root_depth = depth;
+            let previous_evaluation = evaluation;
+
+            // Once per depth, not per root search, so MultiPV lines and re-searches don't wipe the history.
+            // The helpers share the history with the main thread.
+            if self.is_main_thread() {
+                self.move_sorter.lock().unwrap().age_history();
+            }
+            let mut delta = self.options.aspiration_window;
+            let mut failures = 0;
 
-            let (mut alpha, mut beta) = if depth >= 5 {
+            let (mut alpha, mut beta) = if depth >= self.options.aspiration_depth {
                 (evaluation - delta, evaluation + delta)
             } else {
                 (-INFINITY, INFINITY)
@@ -172,14 +437,34 @@ impl Search {
 
                 evaluation = self.evaluation_iteration;
 
-                if evaluation <= alpha {
-                    alpha = alpha.saturating_sub(delta);
-                    delta += delta / 3;
+                if evaluation <= alpha || evaluation >= beta {
+                    self.aspiration_researches += 1;
+                    failures += 1;
 
-                    continue;
-                }
-                if evaluation >= beta {
-                    beta = beta.saturating_add(delta);
+                    if depth > first_depth {
+                        self.extend_on_score_drop(previous_evaluation, evaluation);
+                    }
+
+                    if self.is_main_thread() && self.options.multi_pv == 1 && !self.should_cancel_search() {
+                        let pv = if self.pv_iteration[0] != ChessMove::NULL_MOVE {
+                            self.pv_iteration.clone()
+                        } else {
+                            self.pv.clone()
+                        };
+
+                        self.report(SearchInfo {
+                            evaluation: evaluation as isize,
+                            pv,
+                            bound: if evaluation >= beta { Bound::Lower } else { Bound::Upper },
+                            ..self.search_info(depth)
+                        });
+                    }
+
+                    let max_failures = Search::ASPIRATION_FAILURES;
+                    #[cfg(test)]
+                    let max_failures = self.aspiration_failures;
+
+                    (alpha, beta) = Self::widen_window(alpha, beta, evaluation, delta, failures, max_failures);
                     delta += delta / 3;
 
                     continue;
@@ -188,35 +473,286 @@ impl Search {
                 if self.pv_iteration[0] != ChessMove::NULL_MOVE {
                     self.pv = self.pv_iteration.clone();
                     self.evaluation = self.evaluation_iteration;
+                    self.root_scores = self.root_scores_iteration.clone();
                 }
 
                 break;
             }
 
+            // Cancelled before the first iteration found a move, there is nothing to report.
+            if self.pv.is_empty() {
+                break;
+            }
+
+            if self.is_main_thread() {
+                if self.options.multi_pv > 1 {
+                    self.search_lines(depth, multi_pv);
+
+                    for index in 0..self.lines.len() {
+                        self.report(self.line_info(depth, index));
+                    }
+                } else {
+                    self.report(self.search_info(depth));
+                }
+
+                if self.options.debug {
+                    let pv = Self::legal_prefix(&self.board, &self.pv);
+                    println!("info string pv {}", san::line_to_san(&self.board, &pv));
+                }
+            }
+
+            if depth > first_depth {
+                self.extend_on_score_drop(previous_evaluation, self.evaluation);
+            }
+
+            if self.pv[0] == previous_best_move {
+                stable_iterations += 1;
+            } else {
+                stable_iterations = 0;
+                previous_best_move = self.pv[0];
+            }
+
             let elapsed = self.think_timer.elapsed().as_millis() as usize;
+            if self.is_main_thread()
+                && !self.infinite
+                && !self.options.analyse_mode
+                && !self.pondering.load(Ordering::Relaxed)
+                && self.time_management.stop_iterating(elapsed, stable_iterations, root_moves)
+            {
+                break;
+            }
 
-            let search_info = SearchInfo {
-                depth: depth as usize,
-                seldepth: self.seldepth as usize,
-                time: elapsed,
-                nodes: self.nodes,
-                nps: (self.nodes as f32 * 1000.0 / elapsed.max(1) as f32).round() as usize,
-                evaluation: self.evaluation as isize,
-                _best_move: self.pv[0],
-                pv: self.pv.clone(),
-            };
+            if self.should_cancel_search() || self.mate_found() {
+                break;
+            }
+        }
+
+        if self.is_main_thread() && self.mate.is_some() && !self.mate_found() {
+            println!("info string No mate in {} found", self.mate.unwrap());
+        }
+
+        let best_move = if self.options.move_randomness > 0 {
+            Self::pick_root_move(&self.root_scores, self.options.move_randomness, &mut self.random)
+                .or(self.pv.first().copied())
+        } else {
+            self.pv.first().copied()
+        };
+
+        // Any legal move beats none when the search was stopped early or the move went stale.
+        let best_move = best_move.filter(|&mv| Self::play(&self.board, mv).is_some()).or_else(|| {
+            self.board
+                .generate_moves_vec(!EMPTY)
+                .into_iter()
+                .find(|&mv| self.board.make_move_new(mv).is_ok())
+        });
+
+        while (self.infinite || self.pondering.load(Ordering::Relaxed))
+            && !self.cancelled.load(Ordering::Relaxed)
+        {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        if self.is_main_thread() {
+            match best_move {
+                Some(best_move) => {
+                    if self.options.debug {
+                        println!("info string bestmove {}", san::to_san(&self.board, best_move));
+                    }
+
+                    match self.ponder_move(best_move) {
+                        Some(ponder_move) => println!("bestmove {} ponder {}", best_move, ponder_move),
+                        None => println!("bestmove {}", best_move),
+                    }
+                }
+                // The protocol wants a bestmove even without a move to play.
+                None => println!("bestmove 0000"),
+            }
+        }
+
+        best_move
+    }
+
+    // The reply the PV expects to the best move.
+    pub fn ponder_move(&self, best_move: ChessMove) -> Option<ChessMove> {
+        let pv = Self::legal_prefix(&self.board, &self.pv);
+        let ponder_move = *pv.get(1)?;
+
+        if !self.options.ponder || pv[0] != best_move {
+            return None;
+        }
+
+        Some(ponder_move)
+    }
+
+    pub fn is_main_thread(&self) -> bool {
+        self.thread_id == 0
+    }
+
+    fn report(&mut self, mut info: SearchInfo) {
+        info.pv = Self::legal_prefix(&self.board, &info.pv);
+        info.print();
+
+        #[cfg(test)]
+        self.reported.push(info.to_info().to_string());
+    }
+
+    pub fn search_info(&self, depth: u8) -> SearchInfo {
+        let elapsed = self.think_timer.elapsed().as_millis() as usize;
+
+        SearchInfo {
+            depth: depth as usize,
+            seldepth: self.seldepth as usize,
+            time: elapsed,
+            nodes: self.nodes,
+            nps: (self.nodes as f32 * 1000.0 / elapsed.max(1) as f32).round() as usize,
+            evaluation: self.evaluation as isize,
+            _best_move: self.pv[0],
+            pv: self.pv.clone(),
+
+            multipv: None,
+            hashfull: self.transposition_table.hashfull(),
+
+            bound: Bound::Exact,
+        }
+    }
+
+    pub fn line_info(&self, depth: u8, index: usize) -> SearchInfo {
+        let (evaluation, pv) = &self.lines[index];
 
-            search_info.print();
+        SearchInfo {
+            evaluation: *evaluation as isize,
+            _best_move: pv[0],
+            pv: pv.clone(),
 
-            if self.should_cancel_search() {
+            multipv: Some(index + 1),
+            ..self.search_info(depth)
+        }
+    }
+
+    // Every further line is the best root move once the moves of the earlier lines are excluded.
+    fn search_lines(&mut self, depth: u8, multi_pv: usize) {
+        let mut lines = vec![(self.evaluation, self.pv.clone())];
+        self.excluded_root_moves = vec![self.pv[0]];
+
+        while lines.len() < multi_pv && !self.should_cancel_search() {
+            let score = self.search_base(-INFINITY, INFINITY, depth, 0);
+
+            if self.should_cancel_search() || self.pv_iteration[0] == ChessMove::NULL_MOVE {
                 break;
             }
+
+            lines.push((score, self.pv_iteration.clone()));
+            self.excluded_root_moves.push(self.pv_iteration[0]);
+        }
+
+        self.excluded_root_moves.clear();
+        self.lines = lines;
+    }
+
+    pub fn node_limit_reached(&self) -> bool {
+        self.max_nodes.is_some_and(|max_nodes| self.nodes >= max_nodes)
+    }
+
+    pub fn mate_found(&self) -> bool {
+        self.mate.is_some_and(|mate| {
+            self.evaluation > 0
+                && Eval::mate_score(self.evaluation)
+                && SearchInfo::mate_in_moves(self.evaluation) <= mate as isize
+        })
+    }
+
+    // Only worth reporting once the search is slow enough for somebody to be watching.
+    pub fn currmove_info(&self, mv: ChessMove, number: u8, depth: u8) -> Option<Info> {
+        const CURRMOVE_DELAY: u128 = 1000;
+
+        if !self.options.analyse_mode && self.think_timer.elapsed().as_millis() < CURRMOVE_DELAY {
+            return None;
+        }
+
+        Some(Info {
+            depth: Some(depth as usize),
+            currmove: Some(mv.to_string()),
+            currmovenumber: Some(number as usize),
+            ..Default::default()
+        })
+    }
+
+    pub fn pick_root_move(
+        root_scores: &[(ChessMove, i32)],
+        margin: i32,
+        random: &mut Random,
+    ) -> Option<ChessMove> {
+        let (best_move, best_score) = root_scores.iter().copied().max_by_key(|(_, score)| *score)?;
+
+        // Never gamble with a mate, a mate in 3 and a mate in 4 are only one point apart.
+        if Eval::mate_score(best_score) {
+            return Some(best_move);
+        }
+
+        let candidates = root_scores
+            .iter()
+            .filter(|(_, score)| *score >= best_score - margin)
+            .map(|(mv, _)| *mv)
+            .collect::<Vec<ChessMove>>();
+
+        Some(candidates[random.below(candidates.len())])
+    }
+
+    // Falling this far below the last iteration usually means the best move was refuted,
+    // finding a replacement is worth the time up to the hard limit.
+    fn extend_on_score_drop(&mut self, previous_evaluation: i32, evaluation: i32) {
+        const FAIL_LOW_MARGIN: i32 = 50;
+
+        if previous_evaluation - evaluation > FAIL_LOW_MARGIN {
+            self.time_management.extend();
+        }
+    }
+
+    // A few centipawns at most, so only moves that are about equal can swap places.
+    fn root_noise(&self, mv: ChessMove) -> i32 {
+        if self.options.eval_noise == 0 {
+            return 0;
         }
 
-        println!("bestmove {}", self.pv[0]);
+        let key = (mv.from.to_index() * 64 + mv.to.to_index()) as u64;
+        let mut random = Random::new(self.noise_seed ^ key.wrapping_mul(0x9E3779B97F4A7C15));
+
+        random.below(2 * self.options.eval_noise as usize + 1) as i32 - self.options.eval_noise
+    }
+
+    // Only the failing side is widened, the whole window is opened after repeated failures.
+    pub fn widen_window(
+        alpha: i32,
+        beta: i32,
+        evaluation: i32,
+        delta: i32,
+        failures: u8,
+        max_failures: u8,
+    ) -> (i32, i32) {
+        if failures >= max_failures {
+            (-INFINITY, INFINITY)
+        } else if evaluation <= alpha {
+            (alpha.saturating_sub(delta), beta)
+        } else {
+            (alpha, beta.saturating_add(delta))
+        }
     }
 
     pub fn should_cancel_search(&mut self) -> bool {
+        // The clock only starts running once the opponent played the expected move.
+        if self.was_pondering {
+            if self.pondering.load(Ordering::Relaxed) {
+                return self.cancelled.load(Ordering::Relaxed);
+            }
+
+            self.was_pondering = false;
+            self.think_timer = Instant::now();
+        }
+
+        if self.node_limit_reached() {
+            self.cancelled.store(true, Ordering::Relaxed);
+        }
+
         self.time_management
             .should_cancel_search(self.think_timer, self.cancelled.clone())
     }
@@ -229,27 +765,56 @@ impl Search {
 
         let zobrist_hash = self.board.hash();
 
-        let inserted = if !self.repetition_table.contains(&zobrist_hash) {
-            self.repetition_table.insert(zobrist_hash)
-        } else {
-            false
-        };
+        self.repetition_table.push(zobrist_hash);
+        self.pv_length[ply as usize] = 0;
 
-        self.move_sorter.lock().unwrap().age_history();
+        self.root_scores_iteration.clear();
 
         let first_move = self
             .transposition_table
             .probe(self.board.hash())
-            .map_or(ChessMove::NULL_MOVE, |entry| entry.mv);
+            .map_or(ChessMove::NULL_MOVE, |entry| entry.best_move(&self.board));
+
+        let forced_move = self.forced_move(ply);
+        let restricted = forced_move.is_some()
+            || !self.excluded_root_moves.is_empty()
+            || !self.search_moves.is_empty();
 
         let mut moves = self.board.generate_moves_vec(!EMPTY);
-        self.move_sorter.lock().unwrap().sort_moves(&self.board, &mut moves, first_move, 1);
+        self.move_sorter.lock().unwrap().sort_moves(&self.board, &mut moves, first_move, 1, ChessMove::NULL_MOVE);
         for mv in moves {
+            if forced_move.is_some_and(|forced_move| mv != forced_move)
+                || self.excluded_root_moves.contains(&mv)
+                || (!self.search_moves.is_empty() && !self.search_moves.contains(&mv))
+            {
+                continue;
+            }
+
             if let Ok(node_board) = self.board.make_move_new(mv) {
-                let mut base_pv = [ChessMove::NULL_MOVE; 16];
+                Self::validate_hash(&node_board);
 
                 legal_moves += 1;
-                let score = -self.search(&node_board, -beta, -alpha, depth - 1 + node_board.in_check() as u8, ply + 1, &mut base_pv);
+
+                if self.is_main_thread()
+                    && let Some(info) = self.currmove_info(mv, legal_moves, depth)
+                {
+                    println!("{}", info);
+                }
+
+                // Widen the window by the randomness margin so near-equal moves get real scores.
+                let floor = alpha - self.options.move_randomness - self.options.eval_noise;
+                let extension = self.check_extension(&node_board, depth, ply);
+                self.move_stack[ply as usize] = mv;
+                let mut score = -self.search(&node_board, -beta, -floor, depth - 1 + extension, ply + 1);
+
+                // Even with a negative contempt a repetition must never outrank a move that is still winning.
+                if self.is_repetition(node_board.hash()) {
+                    score = score.min(0);
+                }
+
+                if !Eval::mate_score(score) {
+                    score += self.root_noise(mv);
+                }
 
                 if self.should_cancel_search() {
                     if best_move != ChessMove::NULL_MOVE {
@@ -258,11 +823,13 @@ impl Search {
                         self.pv_iteration.clear();
                         self.pv_iteration.push(ChessMove::NULL_MOVE);
 
-                        if inserted { self.repetition_table.remove(&zobrist_hash); }
+                        self.repetition_table.pop();
                         return 0;
                     }
                 }
 
+                self.root_scores_iteration.push((mv, score));
+
                 if score > max {
                     max = score;
                     best_move = mv;
@@ -270,40 +837,43 @@ impl Search {
                     if score > alpha {
                         alpha = score;
 
-                        self.pv_iteration.clear();
-                        self.pv_iteration.push(mv);
-                        self.pv_iteration.extend_from_slice(&base_pv);
-                        self.pv_iteration.retain(|mv| *mv != ChessMove::NULL_MOVE);
+                        self.update_pv(ply, mv);
+                        self.pv_iteration = self.principal_variation(ply).to_vec();
                     }
                 }
                 if score >= beta {
-                    self.transposition_table.store(
-                        zobrist_hash,
-                        depth,
-                        ply,
-                        score,
-                        best_move,
-                        Bound::Lower,
-                    );
-                    if inserted { self.repetition_table.remove(&zobrist_hash); }
+                    if !restricted {
+                        self.transposition_table.store(
+                            zobrist_hash,
+                            depth,
+                            ply,
+                            score,
+                            best_move,
+                            Bound::Lower,
+                        );
+                    }
+                    self.repetition_table.pop();
 
                     return score;
                 }
             }
         }
 
-        if inserted {
-            self.repetition_table.remove(&zobrist_hash);
-        }
+        self.repetition_table.pop();
 
         if legal_moves == 0 {
             if self.board.in_check() {
                 return -Eval::MATE_SCORE + ply as i32;
             } else {
-                return 0;
+                return self.draw_score(ply);
             }
         }
 
+        // A restricted node doesn't know its real score, so keep it out of the table.
+        if restricted {
+            return max;
+        }
+
         if max <= original_alpha {
             self.transposition_table.store(
                 zobrist_hash,
@@ -334,21 +904,40 @@ impl Search {
         mut beta: i32,
         depth: u8,
         ply: u8,
-        pv: &mut [ChessMove],
     ) -> i32 {
+        self.pv_length[ply as usize] = 0;
+
+        if ply == Search::MAX_PLY {
+            return self.evaluate(board);
+        }
+
         if depth == 0 {
-            return self.search_captures(board, alpha, beta, ply);
+            return self.search_captures(board, alpha, beta, ply, 0);
         }
 
+        self.seldepth = self.seldepth.max(ply);
         self.nodes += 1;
 
         let zobrist_hash = board.hash();
 
-        if board.is_fifty_move() || self.repetition_table.contains(&zobrist_hash) {
-            return 0;
+        if self.is_repetition(zobrist_hash) {
+            return self.draw_score(ply);
+        }
+
+        // Being checkmated by the move that reaches the hundredth halfmove still loses.
+        if board.is_fifty_move() {
+            if board.in_check() && !Self::has_legal_move(board) {
+                return -Eval::MATE_SCORE + ply as i32;
+            }
+
+            return self.draw_score(ply);
+        }
+
+        if Eval::new(board).insufficient_material() {
+            return self.draw_score(ply);
         }
 
-        let inserted = self.repetition_table.insert(zobrist_hash);
+        self.repetition_table.push(zobrist_hash);
 
         let original_alpha = alpha;
         let mut legal_moves: u8 = 0;
@@ -357,36 +946,44 @@ impl Search {
 
         let is_pv = alpha != beta - 1;
 
+        // Mate distance pruning, nothing here can beat a mate that was already found closer to the root.
         alpha = alpha.max(-Eval::MATE_SCORE + ply as i32);
         beta = beta.min(Eval::MATE_SCORE - ply as i32 - 1);
 
         if alpha >= beta {
-            if inserted { self.repetition_table.remove(&zobrist_hash); }
+            self.repetition_table.pop();
             return alpha;
         }
 
+        let forced_move = self.forced_move(ply);
+        let excluded_move = self.excluded_moves[ply as usize];
+
+        // Scores without the excluded move don't belong to this position in the table.
+        let restricted = forced_move.is_some() || excluded_move != ChessMove::NULL_MOVE;
+
         let entry = self.transposition_table.probe(zobrist_hash);
 
-        let tt_mv = entry.map_or(ChessMove::NULL_MOVE, |entry| entry.mv);
+        let mut tt_mv = entry.map_or(ChessMove::NULL_MOVE, |entry| entry.best_move(board));
 
         if let Some(entry) = entry
             && entry.depth >= depth
             && !is_pv
+            && !restricted
         {
             let corrected_score = Self::correct_mate_score(entry.score, ply);
 
             match entry.bound {
                 Bound::Exact => {
-                    if inserted { self.repetition_table.remove(&zobrist_hash); }
+                    self.repetition_table.pop();
                     return corrected_score;
                 }
                 Bound::Lower if corrected_score >= beta => {
-                    if inserted { self.repetition_table.remove(&zobrist_hash); }
+                    self.repetition_table.pop();
 
                     if !board.combined().is_set(tt_mv.to) {
                         self.move_sorter.lock().unwrap().update_history(
-                            tt_mv.to,
-                            unsafe { board.get_piece(tt_mv.from).unwrap_unchecked() },
+                            board.side_to_move,
+                            tt_mv,
                             depth as i16 * depth as i16,
                         );
                     }
@@ -394,59 +991,142 @@ impl Search {
                     return corrected_score;
                 }
                 Bound::Upper if corrected_score <= alpha => {
-                    if inserted { self.repetition_table.remove(&zobrist_hash); }
+                    self.repetition_table.pop();
                     return corrected_score;
                 }
                 _ => {}
             }
         }
 
+        if !restricted && let Some(score) = self.probe_tablebase(board, ply) {
+            self.repetition_table.pop();
+            return score;
+        }
+
         if !is_pv
             && depth > 1
+            && !restricted
             && !board.in_check()
-            && (board.occupancy(board.side_to_move)
-                ^ board.pieces_color(Piece::Pawn, board.side_to_move))
-            .count_ones()
-                != 1
+            && Self::has_non_pawn_material(board)
         {
             if let Ok(node_board) = board.make_null_move_new() {
-                let mut node_pv = [ChessMove::NULL_MOVE; 16];
+                Self::validate_hash(&node_board);
 
                 let reduction = 3 + depth / 6;
+                self.move_stack[ply as usize] = ChessMove::NULL_MOVE;
 
-                let mut score = -self.search(&node_board, -beta, -beta + 1, depth.saturating_sub(reduction), ply + 1, &mut node_pv);
+                let mut score = -self.search(&node_board, -beta, -beta + 1, depth.saturating_sub(reduction), ply + 1);
 
                 if score >= beta {
                     if Eval::mate_score(score) {
                         score = beta;
                     }
 
-                    if inserted { self.repetition_table.remove(&zobrist_hash); }
+                    self.repetition_table.pop();
                     return score;
                 }
             }
         }
 
+        // One ply from the leaves a static eval this far below alpha is left to quiescence to confirm.
+        if !is_pv
+            && depth == 1
+            && !restricted
+            && !board.in_check()
+            && !Eval::mate_score(alpha)
+            && Self::razor(self.evaluate(board), alpha)
+        {
+            let score = self.search_captures(board, alpha, alpha + 1, ply, 0);
+
+            if score <= alpha {
+                self.razor_cutoffs += 1;
+                self.repetition_table.pop();
+                return score;
+            }
+        }
+
+        if tt_mv == ChessMove::NULL_MOVE && depth >= Self::IID_DEPTH && !restricted {
+            // The shallower search visits this same node, which must not look like a repetition.
+            self.repetition_table.pop();
+            tt_mv = self.internal_iterative_deepening(board, alpha, beta, depth, ply);
+            self.repetition_table.push(zobrist_hash);
+        }
+
+        let mut singular_extension = 0;
+        if let Some(entry) = entry
+            && ply > 0
+            && !restricted
+            && depth >= Self::SINGULAR_DEPTH
+            && entry.depth + 3 >= depth
+            && matches!(entry.bound, Bound::Exact | Bound::Lower)
+            && !Eval::mate_score(entry.score)
+            && (ply as u16 + depth as u16) < self.extension_budget()
+        {
+            self.repetition_table.pop();
+            singular_extension = self.singular_extension(board, tt_mv, entry.score, depth, ply);
+            self.repetition_table.push(zobrist_hash);
+        }
+
+        // Near the leaves a quiet move can't make up for a static eval far below alpha.
+        let futile = !is_pv
+            && depth <= 2
+            && forced_move.is_none()
+            && !board.in_check()
+            && !Eval::mate_score(alpha)
+            && Self::futile(self.evaluate(board), alpha, depth);
+
+        // The shallower searches above may have left a line of their own in this ply's row.
+        self.pv_length[ply as usize] = 0;
+
         let mut quiets = Vec::with_capacity(8);
 
-        let mut moves = board.generate_moves_vec(!EMPTY);
-        self.move_sorter.lock().unwrap().sort_moves(board, &mut moves, tt_mv, ply);
+        let previous_move = if ply > 0 { self.move_stack[ply as usize - 1] } else { ChessMove::NULL_MOVE };
+        let moves = MovePicker::new(board, tt_mv, ply, previous_move, self.move_sorter.clone());
         for mv in moves {
+            if forced_move.is_some_and(|forced_move| mv != forced_move) || mv == excluded_move {
+                continue;
+            }
+
+            // Copy-make on purpose: Board is a small Copy type without unmake, so a copy per move
+            // replaces restoring hash, castling and en passant state by hand, and doubles as the legality check.
             if let Ok(node_board) = board.make_move_new(mv) {
-                let mut node_pv = [ChessMove::NULL_MOVE; 16];
+                Self::validate_hash(&node_board);
 
                 let is_quiet = !board.combined().is_set(mv.to);
+
+                legal_moves += 1;
+
+                if futile
+                    && legal_moves > 1
+                    && is_quiet
+                    && mv != tt_mv
+                    && mv.promotion().is_none()
+                    && !node_board.in_check()
+                {
+                    continue;
+                }
+
                 if is_quiet {
                     quiets.push(mv);
                 }
 
-                legal_moves += 1;
+                self.move_stack[ply as usize] = mv;
+
+                let mut extension = self.check_extension(&node_board, depth, ply);
+                if mv == tt_mv {
+                    extension = extension.max(singular_extension);
+                }
 
                 let mut score = i32::MIN;
 
+                let null_window = !is_pv || legal_moves > 1;
+                #[cfg(test)]
+                let null_window = null_window && (!is_pv || self.pvs);
+
                 // Don't reduce on captures, promotions and checks, because of instabilities.
                 if depth >= 3
                     && legal_moves >= 3
+                    && mv != tt_mv
                     && is_quiet
                     && !node_board.in_check()
                     && mv.promotion().is_none()
@@ -457,17 +1137,23 @@ impl Search {
                     let reduction = REDUCTIONS[depth.min(31) as usize][legal_moves.min(31) as usize] - is_pv as u8;
                     let lmr_depth = (depth - 1).saturating_sub(reduction).max(1);
 
-                    score = -self.search(&node_board, -alpha - 1, -alpha, lmr_depth, ply + 1, &mut node_pv);
+                    score = -self.search(&node_board, -alpha - 1, -alpha, lmr_depth, ply + 1);
 
                     if score > alpha {
-                        score = -self.search(&node_board, -alpha - 1, -alpha, depth - 1, ply + 1, &mut node_pv);
+                        score = -self.search(&node_board, -alpha - 1, -alpha, depth - 1, ply + 1);
                     }
-                } else if !is_pv || legal_moves > 1 {
-                    score = -self.search(&node_board, -alpha - 1, -alpha, depth - 1 + node_board.in_check() as u8, ply + 1, &mut node_pv);
+                } else if null_window {
+                    score = -self.search(&node_board, -alpha - 1, -alpha, depth - 1 + extension, ply + 1);
                 }
 
-                if is_pv && (legal_moves == 1 || score > alpha) {
-                    score = -self.search(&node_board, -beta, -alpha, depth - 1 + node_board.in_check() as u8, ply + 1, &mut node_pv);
+                // A null window fail high is already a cutoff, only scores inside the window need the full window.
+                let research = legal_moves > 1 && score > alpha && score < beta;
+                if research {
+                    self.pvs_researches += 1;
+                }
+
+                if is_pv && (!null_window || research) {
+                    score = -self.search(&node_board, -beta, -alpha, depth - 1 + extension, ply + 1);
                 }
 
                 if score > max {
@@ -477,62 +1163,70 @@ impl Search {
                     if score > alpha {
                         alpha = score;
 
-                        pv[0] = mv;
-                        pv[1..].copy_from_slice(&node_pv[..15]);
+                        self.update_pv(ply, mv);
                     }
                 }
                 if score >= beta {
-                    self.transposition_table.store(
-                        zobrist_hash,
-                        depth,
-                        ply,
-                        score,
-                        mv,
-                        Bound::Lower,
-                    );
-                    if inserted { self.repetition_table.remove(&zobrist_hash); }
+                    self.cutoffs += 1;
+                    if legal_moves == 1 {
+                        self.first_move_cutoffs += 1;
+                    }
 
-                    if is_quiet {
-                        self.move_sorter.lock().unwrap().update_history(
-                            mv.to,
-                            unsafe { board.get_piece(mv.from).unwrap_unchecked() },
-                            depth as i16 * depth as i16,
+                    if !restricted {
+                        self.transposition_table.store(
+                            zobrist_hash,
+                            depth,
+                            ply,
+                            score,
+                            mv,
+                            Bound::Lower,
                         );
+                    }
+                    self.repetition_table.pop();
 
+                    if is_quiet {
                         quiets.pop();
-                        for quiet in quiets {
-                            self.move_sorter.lock().unwrap().update_history(
-                                quiet.to,
-                                unsafe { board.get_piece(quiet.from).unwrap_unchecked() },
-                                -2 * depth as i16,
-                            );
-                        }
-
-                        self.move_sorter.lock().unwrap().add_killer_move(mv, ply);
+
+                        let mut move_sorter = self.move_sorter.lock().unwrap();
+                        move_sorter.update_quiet_histories(
+                            board,
+                            mv,
+                            &quiets,
+                            depth,
+                            self.options.history_malus,
+                            previous_move,
+                        );
+                        move_sorter.add_killer_move(mv, ply);
+                        move_sorter.add_countermove(previous_move, mv);
                     }
 
                     return score;
                 }
 
-                if self.nodes & 1023 == 0 && self.should_cancel_search() {
-                    if inserted { self.repetition_table.remove(&zobrist_hash); }
+                if (self.nodes & 1023 == 0 || self.node_limit_reached()) && self.should_cancel_search() {
+                    self.repetition_table.pop();
 
                     return max;
                 }
             }
         }
 
-        if inserted { self.repetition_table.remove(&zobrist_hash); }
+        self.repetition_table.pop();
+
+        // Nothing but the excluded move was legal, which makes it singular.
+        if legal_moves == 0 && excluded_move != ChessMove::NULL_MOVE {
+            return alpha;
+        }
 
         if legal_moves == 0 {
             if board.in_check() {
                 return -Eval::MATE_SCORE + ply as i32;
             } else {
-                return 0;
+                return self.draw_score(ply);
             }
         }
 
-        if best_move != ChessMove::NULL_MOVE {
+        if best_move != ChessMove::NULL_MOVE && !restricted {
             if max <= original_alpha {
                 self.transposition_table.store(
                     zobrist_hash,
@@ -557,13 +1251,27 @@ impl Search {
         max
     }
 
-    fn search_captures(&mut self, board: &Board, mut alpha: i32, beta: i32, ply: u8) -> i32 {
+    fn search_captures(&mut self, board: &Board, mut alpha: i32, beta: i32, ply: u8, qsearch_ply: u8) -> i32 {
+        self.pv_length[ply as usize] = 0;
         self.seldepth = self.seldepth.max(ply);
+        self.max_qsearch_ply = self.max_qsearch_ply.max(qsearch_ply);
         self.nodes += 1;
 
-        let stand_pat = Eval::new(board).eval();
-        if stand_pat >= beta {
-            return stand_pat;
+        // Long capture chains are cut off here, trusting the static eval even in check.
+        if qsearch_ply >= Self::MAX_QSEARCH_PLY || ply == Search::MAX_PLY {
+            return self.evaluate(board);
+        }
+
+        // Standing pat is unsound in check, every evasion has to be searched instead.
+        let in_check = board.in_check();
+
+        let stand_pat = if in_check {
+            -Eval::MATE_SCORE + ply as i32
+        } else {
+            self.evaluate(board)
+        };
+        if stand_pat >= beta {
+            return stand_pat;
         }
         if stand_pat > alpha {
             alpha = stand_pat;
@@ -574,31 +1282,46 @@ impl Search {
         const FUTILITY_MARGIN: i32 = 170;
         let futility_base = stand_pat + FUTILITY_MARGIN;
 
-        let mut moves = board.generate_moves_vec(board.occupancy(!board.side_to_move));
-        self.move_sorter.lock().unwrap().sort_moves(board, &mut moves, ChessMove::NULL_MOVE, ply);
+        let delta_pruning = !in_check && Self::delta_pruning_allowed(board);
+        #[cfg(test)]
+        let delta_pruning = delta_pruning && self.delta_pruning;
+
+        let mask = if in_check {
+            !EMPTY
+        } else {
+            board.occupancy(!board.side_to_move)
+        };
+
+        let mut moves = board.generate_moves_vec(mask);
+        self.move_sorter.lock().unwrap().sort_moves(board, &mut moves, ChessMove::NULL_MOVE, ply, ChessMove::NULL_MOVE);
         for mv in moves {
             if let Ok(node_board) = board.make_move_new(mv) {
-                if let Some(captured) = board.get_piece(mv.to) {
-                    let futility_score = futility_base + PIECE_VALUES_EG[captured.to_index()];
+                Self::validate_hash(&node_board);
+
+                if !in_check && let Some(captured) = board.get_piece(mv.to) {
+                    let mut futility_score = futility_base + PIECE_VALUES_EG[captured.to_index()];
 
-                    if futility_score <= alpha
-                        && !node_board.in_check()
-                        && mv.promotion().is_none()
-                    {
+                    if let Some(promotion) = mv.promotion() {
+                        futility_score += PIECE_VALUES_EG[promotion.to_index()] - PIECE_VALUES_EG[Piece::Pawn.to_index()];
+                    }
+
+                    if delta_pruning && futility_score <= alpha && !node_board.in_check() {
                         max = max.max(futility_score);
                         continue;
                     }
 
-                    if futility_base <= alpha
-                        && !node_board.in_check()
-                        && MoveSorter::see(board, mv) < 0
-                    {
-                        max = max.max(futility_base);
+                    // A capture that loses material rarely raises alpha here, unless it gives check.
+                    let losing = !node_board.in_check() && MoveSorter::see(board, mv) < 0;
+                    #[cfg(test)]
+                    let losing = losing && self.see_pruning;
+
+                    if losing {
+                        self.see_prunes += 1;
                         continue;
                     }
                 }
 
-                let score = -self.search_captures(&node_board, -beta, -alpha, ply + 1);
+                let score = -self.search_captures(&node_board, -beta, -alpha, ply + 1, qsearch_ply + 1);
 
                 if score > max {
                     max = score;
@@ -616,6 +1339,211 @@ impl Search {
         max
     }
 
+    fn evaluate(&mut self, board: &Board) -> i32 {
+        // The evaluation drifts with the halfmove clock, which the zobrist hash doesn't cover.
+        let key = board.hash() ^ (board.half_move_clock as u64).wrapping_mul(0x9E3779B97F4A7C15);
+        let index = key as usize & (EVAL_CACHE_SIZE - 1);
+
+        let (cached_key, cached_eval) = self.eval_cache[index];
+        if cached_key == key {
+            self.eval_cache_hits += 1;
+            return cached_eval;
+        }
+
+        let eval = Eval::with_pawn_hash_table(board, &mut self.pawn_hash_table).eval();
+        self.eval_cache[index] = (key, eval);
+
+        eval
+    }
+
+    // Recomputing the hash from scratch is slow, so this only runs with the hash-validation feature.
+    #[cfg(all(debug_assertions, feature = "hash-validation"))]
+    fn validate_hash(board: &Board) {
+        let recomputed = Board::from_fen(&board.to_fen()).hash();
+
+        debug_assert_eq!(
+            recomputed,
+            board.hash(),
+            "incremental hash diverged from the recomputed hash in {}",
+            board.to_fen()
+        );
+    }
+
+    #[cfg(not(all(debug_assertions, feature = "hash-validation")))]
+    #[inline(always)]
+    fn validate_hash(_board: &Board) {}
+
+    // With little material left a single capture can decide the game, so don't prune them blindly.
+    fn delta_pruning_allowed(board: &Board) -> bool {
+        const ENDGAME_PHASE: i32 = 192;
+
+        Eval::calculate_game_phase(board) < ENDGAME_PHASE
+    }
+
+    // Without a TT move the ordering is poor, so a shallower search first finds one.
+    fn internal_iterative_deepening(&mut self, board: &Board, alpha: i32, beta: i32, depth: u8, ply: u8) -> ChessMove {
+        self.search(board, alpha, beta, depth - 2, ply);
+
+        self.transposition_table
+            .probe(board.hash())
+            .map_or(ChessMove::NULL_MOVE, |entry| entry.best_move(board))
+    }
+
+    // The TT move is singular when every other move fails low at half depth against a bound just below its score.
+    fn singular_extension(&mut self, board: &Board, tt_mv: ChessMove, tt_score: i32, depth: u8, ply: u8) -> u8 {
+        let singular_beta = tt_score - 2 * depth as i32;
+
+        self.excluded_moves[ply as usize] = tt_mv;
+        let score = self.search(board, singular_beta - 1, singular_beta, (depth - 1) / 2, ply);
+        self.excluded_moves[ply as usize] = ChessMove::NULL_MOVE;
+
+        if score < singular_beta {
+            self.singular_extensions += 1;
+            1
+        } else {
+            0
+        }
+    }
+
+    // The line of the node at `ply` becomes its best move followed by the line of the child below it.
+    fn update_pv(&mut self, ply: u8, mv: ChessMove) {
+        let ply = ply as usize;
+        let child_length = self.pv_length[ply + 1];
+
+        let (rows, child_rows) = self.pv_table.split_at_mut(ply + 1);
+        let row = &mut rows[ply];
+
+        row[0] = mv;
+        row[1..=child_length].copy_from_slice(&child_rows[0][..child_length]);
+        self.pv_length[ply] = child_length + 1;
+    }
+
+    pub fn principal_variation(&self, ply: u8) -> &[ChessMove] {
+        &self.pv_table[ply as usize][..self.pv_length[ply as usize]]
+    }
+
+    // TT moves from another position can end up in the PV, GUIs only get the part that can be played.
+    pub fn legal_prefix(board: &Board, pv: &[ChessMove]) -> Vec<ChessMove> {
+        let mut board = *board;
+        let mut legal = Vec::with_capacity(pv.len());
+
+        for &mv in pv {
+            let Some(next_board) = Self::play(&board, mv) else {
+                break;
+            };
+
+            board = next_board;
+            legal.push(mv);
+        }
+
+        legal
+    }
+
+    fn play(board: &Board, mv: ChessMove) -> Option<Board> {
+        if mv == ChessMove::NULL_MOVE || !board.generate_moves_vec(BitBoard(1 << mv.to.to_index())).contains(&mv) {
+            return None;
+        }
+
+        board.make_move_new(mv).ok()
+    }
+
+    fn razor(static_eval: i32, alpha: i32) -> bool {
+        const RAZOR_MARGIN: i32 = 300;
+
+        static_eval + RAZOR_MARGIN < alpha
+    }
+
+    fn futile(static_eval: i32, alpha: i32, depth: u8) -> bool {
+        const FUTILITY_MARGIN: i32 = 100;
+
+        static_eval + FUTILITY_MARGIN * depth as i32 <= alpha
+    }
+
+    // Extend checks, but stop once a line reaches twice the root depth so check sequences can't run away.
+    fn check_extension(&self, node_board: &Board, depth: u8, ply: u8) -> u8 {
+        (node_board.in_check() && (ply as u16 + depth as u16) < self.extension_budget()) as u8
+    }
+
+    // Extended lines reach at most twice the root depth, and never the end of the per-ply tables.
+    fn extension_budget(&self) -> u16 {
+        (2 * self.root_depth as u16).min(Search::MAX_PLY as u16 - 1)
+    }
+
+    fn legal_move_count(board: &Board) -> usize {
+        board
+            .generate_moves_vec(!EMPTY)
+            .into_iter()
+            .filter(|&mv| board.make_move_new(mv).is_ok())
+            .count()
+    }
+
+    fn has_legal_move(board: &Board) -> bool {
+        board
+            .generate_moves_vec(!EMPTY)
+            .into_iter()
+            .any(|mv| board.make_move_new(mv).is_ok())
+    }
+
+    // Null moves are unsound in zugzwang, which is mostly a king and pawns problem.
+    fn has_non_pawn_material(board: &Board) -> bool {
+        let side = board.side_to_move;
+
+        (board.occupancy(side) ^ board.pieces_color(Piece::Pawn, side)).count_ones() != 1
+    }
+
+    // Repeating a position of the search path is treated as a draw right away, since the side
+    // that allowed it could repeat again, but the game history needs a real threefold repetition.
+    // The root was reached in the game, so returning to it counts as a game occurrence.
+    fn is_repetition(&self, zobrist_hash: u64) -> bool {
+        let mut occurrences = 0;
+
+        for (index, &hash) in self.repetition_table.iter().enumerate().rev() {
+            if hash == zobrist_hash {
+                if index > self.game_history {
+                    return true;
+                }
+
+                occurrences += 1;
+                if occurrences >= 2 {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    // The tablebase result stands only right after a capture or pawn move, later the fifty move rule may change it.
+    fn probe_tablebase(&mut self, board: &Board, ply: u8) -> Option<i32> {
+        let tablebases = self.tablebases.as_ref()?;
+
+        if board.half_move_clock != 0 || board.combined().count_ones() as usize > tablebases.max_pieces() {
+            return None;
+        }
+
+        let score = match tablebases.probe_wdl(board)? {
+            Wdl::Win => TB_WIN_SCORE - ply as i32,
+            Wdl::Loss => -TB_WIN_SCORE + ply as i32,
+            Wdl::Draw => self.draw_score(ply),
+        };
+        self.tablebase_hits += 1;
+
+        Some(score)
+    }
+
+    // Contempt makes a draw look slightly bad for the side to move at the root and good for its opponent.
+    fn draw_score(&self, ply: u8) -> i32 {
+        if ply % 2 == 0 {
+            -self.options.contempt
+        } else {
+            self.options.contempt
+        }
+    }
+
+    fn forced_move(&self, ply: u8) -> Option<ChessMove> {
+        self.forced_line.get(ply as usize).copied()
+    }
+
     fn correct_mate_score(score: i32, ply: u8) -> i32 {
         if Eval::mate_score(score) {
             let sign = score.signum();
@@ -624,3 +1552,1361 @@ impl Search {
         score
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chessframe::{color::Color, square::Square};
+
+    use super::*;
+    use crate::transposition_table::Replacement;
+
+    // A fresh search with its own table and move sorter, for tests that only change the limits or options.
+    fn search_with(board: Board, limits: SearchLimits, options: Options) -> Search {
+        Search::new(
+            board,
+            limits,
+            Vec::new(),
+            Arc::new(TranspositionTable::with_size_mb(1)),
+            Arc::new(Mutex::new(MoveSorter::new())),
+            Arc::new(AtomicBool::new(false)),
+            options,
+        )
+    }
+
+    fn search(fen: &str, depth: u8) -> Search {
+        search_with(
+            Board::from_fen(fen),
+            SearchLimits {
+                depth: Some(depth),
+                ..Default::default()
+            },
+            Options::default(),
+        )
+    }
+
+    #[test]
+    #[cfg(feature = "hash-validation")]
+    fn incremental_hash_matches_recomputed_hash_during_search() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbqkb1r/pp1p1ppp/5n2/2pPp3/8/8/PPP1PPPP/RNBQKBNR w KQkq e6 0 4",
+        ];
+
+        for fen in fens {
+            search(fen, 5).start_search();
+        }
+    }
+
+    #[test]
+    fn deterministic_hash_fills_the_table_identically() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+        let run = || {
+            let mut transposition_table = TranspositionTable::with_size_mb(1);
+            transposition_table.set_replacement(Replacement::Always);
+            let transposition_table = Arc::new(transposition_table);
+
+            Search::new(
+                Board::from_fen(fen),
+                SearchLimits {
+                    depth: Some(4),
+                    ..Default::default()
+                },
+                Vec::new(),
+                transposition_table.clone(),
+                Arc::new(Mutex::new(MoveSorter::new())),
+                Arc::new(AtomicBool::new(false)),
+                Options::default(),
+            )
+            .start_search();
+
+            transposition_table
+        };
+
+        let first = run();
+        let second = run();
+
+        assert!(
+            first.entries() == second.entries(),
+            "expected two identical searches to leave identical transposition tables"
+        );
+    }
+
+    #[test]
+    fn forced_line_is_followed_before_searching_freely() {
+        let mut search = search_with(
+            Board::default(),
+            SearchLimits {
+                depth: Some(3),
+                ..Default::default()
+            },
+            Options {
+                forced_line: vec!["a2a3".to_string(), "h7h6".to_string()],
+                ..Default::default()
+            },
+        );
+
+        search.start_search();
+
+        assert!(
+            search.pv.len() >= 2
+                && search.pv[0] == ChessMove::new(Square::A2, Square::A3)
+                && search.pv[1] == ChessMove::new(Square::H7, Square::H6),
+            "expected the principal variation to start with the forced line"
+        );
+    }
+
+    #[test]
+    fn infinite_search_waits_for_stop() {
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let mut search = Search::new(
+            Board::default(),
+            SearchLimits {
+                depth: Some(2),
+                infinite: true,
+                ..Default::default()
+            },
+            Vec::new(),
+            Arc::new(TranspositionTable::with_size_mb(1)),
+            Arc::new(Mutex::new(MoveSorter::new())),
+            cancelled.clone(),
+            Options::default(),
+        );
+
+        let handle = thread::spawn(move || search.start_search());
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(!handle.is_finished(), "expected an infinite search to wait for stop");
+
+        cancelled.store(true, Ordering::Relaxed);
+
+        assert!(handle.join().unwrap().is_some(), "expected a best move after stop");
+    }
+
+    #[test]
+    fn search_cancelled_before_it_starts_still_plays_a_legal_move() {
+        let mut search = search("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 5);
+        let board = search.board;
+        search.cancelled.store(true, Ordering::Relaxed);
+
+        let best_move = search.start_search();
+
+        assert!(
+            best_move.is_some_and(|mv| board.make_move_new(mv).is_ok()),
+            "expected a legal fallback move from a search stopped before its first iteration"
+        );
+        assert!(search.reported.is_empty(), "expected no info line without a searched move");
+    }
+
+    #[test]
+    fn mated_root_has_no_best_move() {
+        let mut search = search("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3", 3);
+
+        assert!(search.start_search().is_none(), "expected no move to play when mated");
+    }
+
+    #[test]
+    fn single_legal_move_returns_at_once() {
+        let mut search = search_with(
+            Board::from_fen("7k/5K2/8/8/8/8/8/8 b - - 0 1"),
+            SearchLimits {
+                time_management: TimeManagement::new(None, Some(60_000), None, None),
+                ..Default::default()
+            },
+            Options::default(),
+        );
+
+        let timer = Instant::now();
+        let best_move = search.start_search().unwrap();
+
+        assert!(best_move == ChessMove::new(Square::H8, Square::H7), "expected the only legal move");
+        assert!(
+            timer.elapsed() < Duration::from_millis(100),
+            "expected no thinking about a forced move with 3 seconds allotted"
+        );
+    }
+
+    #[test]
+    fn score_drop_extends_the_allotment() {
+        let timed = || {
+            let mut search = search("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 1);
+            search.time_management = TimeManagement::new(None, Some(10_000), None, None);
+            search.think_timer = Instant::now() - Duration::from_millis(600);
+            search
+        };
+
+        let mut steady = timed();
+        steady.extend_on_score_drop(30, 10);
+        assert!(steady.should_cancel_search(), "expected a small dip to keep the 500ms allotment");
+
+        let mut refuted = timed();
+        refuted.extend_on_score_drop(30, -120);
+        assert!(
+            !refuted.should_cancel_search() && refuted.time_management.time() > 500,
+            "expected a refuted best move to search past its 500ms allotment"
+        );
+    }
+
+    #[test]
+    fn helpers_leave_the_clock_to_the_main_thread() {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let limits = SearchLimits {
+            time_management: TimeManagement::new(None, Some(2_000), None, None),
+            ..Default::default()
+        };
+
+        let mut helper = Search::new(
+            Board::default(),
+            limits.for_helper(),
+            Vec::new(),
+            Arc::new(TranspositionTable::with_size_mb(1)),
+            Arc::new(Mutex::new(MoveSorter::new())),
+            cancelled.clone(),
+            Options::default(),
+        );
+        helper.thread_id = 1;
+        let handle = thread::spawn(move || helper.start_search());
+
+        // Well past the 100ms allotment, which the main thread may have extended.
+        thread::sleep(Duration::from_millis(300));
+        assert!(!cancelled.load(Ordering::Relaxed), "expected the helper to never stop the search on its own");
+
+        cancelled.store(true, Ordering::Relaxed);
+        assert!(handle.join().unwrap().is_some(), "expected the helper to stop once the main thread is done");
+    }
+
+    #[test]
+    fn ponder_move_is_the_expected_reply() {
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4";
+
+        let mut search = search(fen, 4);
+        let best_move = search.start_search().unwrap();
+        assert!(search.ponder_move(best_move).is_none(), "expected no ponder move unless Ponder is on");
+
+        let mut search = search_with(
+            Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            SearchLimits {
+                depth: Some(4),
+                ..Default::default()
+            },
+            Options {
+                ponder: true,
+                ..Default::default()
+            },
+        );
+        let best_move = search.start_search().unwrap();
+        let ponder_move = search.ponder_move(best_move).unwrap();
+
+        let after = search.board.make_move_new(best_move).unwrap();
+        assert!(after.make_move_new(ponder_move).is_ok(), "expected a legal reply to the best move");
+    }
+
+    #[test]
+    fn analyse_mode_thinks_about_forced_moves() {
+        let mut search = search_with(
+            Board::from_fen("7k/5K2/8/8/8/8/8/8 b - - 0 1"),
+            SearchLimits {
+                depth: Some(4),
+                time_management: TimeManagement::new(None, Some(60_000), None, None),
+                ..Default::default()
+            },
+            Options {
+                analyse_mode: true,
+                contempt: 50,
+                ..Default::default()
+            },
+        );
+        search.start_search();
+
+        assert!(search.root_depth == 4, "expected analysis to skip the easy move exit");
+        assert!(search.options.contempt == 0, "expected analysis to drop the contempt");
+        assert!(search.currmove_info(ChessMove::new(Square::H8, Square::H7), 1, 4).is_some());
+    }
+
+    #[test]
+    fn aspiration_window_size_sets_the_researches() {
+        let researches = |aspiration_window: i32| {
+            crate::bench::BENCH_FENS[..2]
+                .iter()
+                .map(|fen| {
+                    let mut search = search_with(
+                        Board::from_fen(fen),
+                        SearchLimits {
+                            depth: Some(7),
+                            ..Default::default()
+                        },
+                        Options {
+                            aspiration_window,
+                            ..Default::default()
+                        },
+                    );
+                    search.start_search();
+
+                    search.aspiration_researches
+                })
+                .sum::<usize>()
+        };
+
+        let default = researches(Options::default().aspiration_window);
+
+        assert!(researches(100_000) == 0, "expected a huge window to never fail");
+        assert!(researches(1) > default, "expected a tiny window to fail more often");
+    }
+
+    #[test]
+    fn move_time_search_returns_in_time() {
+        let mut search = search_with(
+            Board::default(),
+            SearchLimits {
+                time_management: TimeManagement::new(Some(100), None, None, None),
+                ..Default::default()
+            },
+            Options::default(),
+        );
+
+        let timer = Instant::now();
+        search.start_search();
+
+        assert!(
+            timer.elapsed() < Duration::from_millis(500),
+            "expected a 100ms movetime search to return well within 500ms"
+        );
+    }
+
+    #[test]
+    fn search_info_reports_depth_seldepth_and_the_full_pv() {
+        let mut search = search("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 4);
+        search.start_search();
+
+        let search_info = search.search_info(4);
+        let info = search_info.to_info().to_string();
+
+        assert!(search_info.seldepth >= 4, "expected the selective depth to reach the nominal depth");
+        assert!(search_info.pv.len() > 1, "expected more than just the best move in the pv");
+        assert!(
+            info.contains("depth 4") && info.contains("seldepth") && info.contains("nps"),
+            "expected the info line to report depth, seldepth and nps"
+        );
+    }
+
+    #[test]
+    fn aspiration_window_widens_only_the_failing_side() {
+        assert!(
+            Search::widen_window(-16, 16, -40, 16, 1, Search::ASPIRATION_FAILURES) == (-32, 16),
+            "expected a fail low to only lower alpha"
+        );
+        assert!(
+            Search::widen_window(-16, 16, 40, 16, 1, Search::ASPIRATION_FAILURES) == (-16, 32),
+            "expected a fail high to only raise beta"
+        );
+        assert!(
+            Search::widen_window(-16, 16, 40, 16, Search::ASPIRATION_FAILURES, Search::ASPIRATION_FAILURES)
+                == (-INFINITY, INFINITY),
+            "expected the full window after repeated failures"
+        );
+    }
+
+    #[test]
+    fn widening_the_window_searches_fewer_nodes_than_reopening_it() {
+        // Nodes and aspiration re-searches over the whole suite.
+        let nodes = |aspiration_failures: u8| {
+            crate::bench::BENCH_FENS[..3].iter().fold((0, 0), |(nodes, researches), fen| {
+                let mut search = search(fen, 7);
+                search.aspiration_failures = aspiration_failures;
+                search.start_search();
+
+                (nodes + search.nodes, researches + search.aspiration_researches)
+            })
+        };
+
+        let (widened, researches) = nodes(Search::ASPIRATION_FAILURES);
+        let (reopened, _) = nodes(1);
+
+        assert!(researches > 0, "expected the suite to fail an aspiration window");
+        assert!(
+            widened < reopened,
+            "expected widening the failing side to save nodes, got {} against {}",
+            widened,
+            reopened
+        );
+    }
+
+    #[test]
+    fn quiescence_depth_is_bounded() {
+        // Every piece bears on the center, so the capture sequences run long.
+        let mut search = search("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 1);
+        let board = search.board;
+
+        search.search_captures(&board, -INFINITY, INFINITY, 0, 0);
+        assert!(search.max_qsearch_ply > 2, "expected the captures to chain");
+        assert!(
+            search.max_qsearch_ply <= Search::MAX_QSEARCH_PLY,
+            "expected quiescence to stop at {} plies, reached {}",
+            Search::MAX_QSEARCH_PLY,
+            search.max_qsearch_ply
+        );
+
+        let cut_off = search.search_captures(&board, -INFINITY, INFINITY, 0, Search::MAX_QSEARCH_PLY);
+        assert!(cut_off == Eval::new(&board).eval(), "expected the static eval at the limit");
+    }
+
+    #[test]
+    fn quiescence_does_not_stand_pat_in_check() {
+        let mated = "k7/8/8/8/8/8/5PPP/r5K1 w - - 0 1";
+        let escapes = "k7/8/8/8/8/8/6PP/r5K1 w - - 0 1";
+
+        let mut mated_search = search(mated, 1);
+        let board = mated_search.board;
+        let score = mated_search.search_captures(&board, -INFINITY, INFINITY, 0, 0);
+
+        assert!(
+            score == -Eval::MATE_SCORE,
+            "expected quiescence to see the back rank mate"
+        );
+
+        let mut escapes_search = search(escapes, 1);
+        let board = escapes_search.board;
+        let score = escapes_search.search_captures(&board, -INFINITY, INFINITY, 0, 0);
+
+        assert!(
+            !Eval::mate_score(score),
+            "expected quiescence to find the quiet king escape"
+        );
+    }
+
+    #[test]
+    fn delta_pruning_is_disabled_in_the_endgame() {
+        let middlegame = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let endgame = Board::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1");
+
+        assert!(Search::delta_pruning_allowed(&middlegame));
+        assert!(
+            !Search::delta_pruning_allowed(&endgame),
+            "expected no delta pruning with only rooks and pawns left"
+        );
+    }
+
+    #[test]
+    fn delta_pruning_saves_nodes_but_keeps_promotions() {
+        let nodes = |delta_pruning: bool| {
+            [
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+                "rnb1kbnr/pppp1ppp/8/4p1q1/4P3/3P4/PPP2PPP/RNBQKBNR w KQkq - 0 3",
+            ]
+            .iter()
+            .map(|fen| {
+                let mut search = search(fen, 4);
+                search.delta_pruning = delta_pruning;
+                search.start_search();
+
+                search.nodes
+            })
+            .sum::<usize>()
+        };
+
+        let (pruned, unpruned) = (nodes(true), nodes(false));
+        assert!(pruned < unpruned, "expected delta pruning to save nodes, got {} against {}", pruned, unpruned);
+
+        // Taking the rook alone can't raise alpha, taking it with a promotion can.
+        let promotion = |delta_pruning: bool| {
+            let mut search = search("r1n4k/1P6/8/6q1/8/8/8/1K1Q4 w - - 0 1", 1);
+            search.delta_pruning = delta_pruning;
+            let board = search.board;
+
+            search.search_captures(&board, 0, 1, 0, 0)
+        };
+
+        assert!(
+            promotion(true) >= 1 && promotion(false) >= 1,
+            "expected the promoting capture to be searched with delta pruning"
+        );
+    }
+
+    #[test]
+    fn repeated_position_is_scored_as_a_draw() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1");
+        let repeated = board.make_move_new(ChessMove::new(Square::E1, Square::F1)).unwrap();
+
+        let mut search = search("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", 3);
+        search.repetition_table = vec![repeated.hash(), repeated.hash()];
+        search.game_history = 2;
+
+        let score = search.search(&repeated, -INFINITY, INFINITY, 3, 1);
+
+        assert!(score == 0, "expected a third occurrence of a position from the game history to be a draw");
+    }
+
+    #[test]
+    fn twofold_repetition_of_the_game_history_is_not_a_draw() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1");
+        let repeated = board.make_move_new(ChessMove::new(Square::E1, Square::F1)).unwrap();
+
+        let mut search = search("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", 3);
+        search.repetition_table = vec![repeated.hash()];
+        search.game_history = 1;
+
+        assert!(!search.is_repetition(repeated.hash()), "expected a single earlier occurrence to not be a draw yet");
+
+        search.repetition_table.push(board.hash());
+        assert!(
+            !search.is_repetition(board.hash()),
+            "expected a return to the root to need an earlier occurrence in the game"
+        );
+
+        let node = board.make_move_new(ChessMove::new(Square::D1, Square::D2)).unwrap();
+        search.repetition_table.push(node.hash());
+        assert!(
+            search.is_repetition(node.hash()),
+            "expected a repetition of the search path to be a draw"
+        );
+    }
+
+    #[test]
+    fn engine_move_completing_a_threefold_is_a_draw() {
+        let mut board = Board::default();
+        let mut history = Vec::new();
+
+        for mv in ["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1"] {
+            history.push(board.hash());
+            board = board.make_move_new(board.infer_move(mv).unwrap()).unwrap();
+        }
+
+        let mut search = Search::new(
+            board,
+            SearchLimits::default(),
+            history,
+            Arc::new(TranspositionTable::with_size_mb(1)),
+            Arc::new(Mutex::new(MoveSorter::new())),
+            Arc::new(AtomicBool::new(false)),
+            Options {
+                contempt: 50,
+                ..Default::default()
+            },
+        );
+
+        search.search_base(-INFINITY, INFINITY, 2, 0);
+
+        let repeating = board.infer_move("f6g8").unwrap();
+        let (_, score) = search
+            .root_scores_iteration
+            .iter()
+            .copied()
+            .find(|(mv, _)| *mv == repeating)
+            .unwrap();
+
+        assert!(score == -50, "expected the third occurrence of the start position to be a draw");
+    }
+
+    #[test]
+    fn fifty_move_rule_draws_on_the_hundredth_halfmove() {
+        let mut before = search("4k3/8/8/8/8/8/8/3QK3 w - - 99 80", 1);
+        let board = before.board;
+        assert!(
+            before.search(&board, -INFINITY, INFINITY, 1, 1) > 300,
+            "expected the queen to still count on the 99th halfmove"
+        );
+
+        let mut after = search("4k3/8/8/8/8/8/8/3QK3 w - - 100 80", 1);
+        let board = after.board;
+        assert!(
+            after.search(&board, -INFINITY, INFINITY, 1, 1) == 0,
+            "expected a draw on the 100th halfmove"
+        );
+    }
+
+    #[test]
+    fn checkmate_on_the_hundredth_halfmove_is_not_a_draw() {
+        let mut search = search("k7/8/8/8/8/8/5PPP/r5K1 w - - 100 80", 1);
+        let board = search.board;
+
+        assert!(
+            search.search(&board, -INFINITY, INFINITY, 1, 1) == -Eval::MATE_SCORE + 1,
+            "expected the mate to take precedence over the fifty move rule"
+        );
+    }
+
+    #[test]
+    fn pvs_searches_fewer_nodes_than_full_windows() {
+        let nodes = |pvs: bool| {
+            crate::bench::BENCH_FENS[..3]
+                .iter()
+                .map(|fen| {
+                    let mut search = search(fen, 5);
+                    search.pvs = pvs;
+                    search.start_search();
+
+                    search.nodes
+                })
+                .sum::<usize>()
+        };
+
+        let (with_pvs, without_pvs) = (nodes(true), nodes(false));
+        assert!(
+            with_pvs < without_pvs,
+            "expected null windows to save nodes, got {} against {}",
+            with_pvs,
+            without_pvs
+        );
+    }
+
+    #[test]
+    fn pvs_rarely_needs_a_re_search() {
+        let mut search = search("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 5);
+        search.start_search();
+
+        assert!(
+            search.pvs_researches * 4 < search.nodes,
+            "expected the null window searches to mostly hold with decent move ordering"
+        );
+    }
+
+    #[test]
+    fn check_extensions_stay_within_the_budget() {
+        // Both queens can check the exposed kings over and over.
+        let mut search = search("7k/8/3q4/8/8/8/Q7/7K w - - 0 1", 6);
+        search.start_search();
+
+        let budget = 2 * search.root_depth as u16;
+        assert!(
+            (search.seldepth as u16) <= budget + Search::MAX_QSEARCH_PLY as u16,
+            "expected the main search to stop within {} plies before quiescence, got seldepth {}",
+            budget,
+            search.seldepth
+        );
+
+        let in_check = Board::from_fen("7k/8/8/8/8/8/8/Q6K b - - 0 1");
+        assert!(search.check_extension(&in_check, 1, 0) == 1, "expected a check to be extended");
+        assert!(
+            search.check_extension(&in_check, 1, 2 * search.root_depth) == 0,
+            "expected no extension past twice the root depth"
+        );
+
+        search.root_depth = 200;
+        assert!(search.check_extension(&in_check, 1, 252) == 1);
+        assert!(
+            search.check_extension(&in_check, 1, 253) == 0,
+            "expected deep searches to keep their extensions clear of the last ply"
+        );
+    }
+
+    #[test]
+    fn mate_distance_survives_the_transposition_table() {
+        let transposition_table = Arc::new(TranspositionTable::with_size_mb(1));
+
+        let run = |fen: &str| {
+            let mut search = Search::new(
+                Board::from_fen(fen),
+                SearchLimits {
+                    depth: Some(5),
+                    ..Default::default()
+                },
+                Vec::new(),
+                transposition_table.clone(),
+                Arc::new(Mutex::new(MoveSorter::new())),
+                Arc::new(AtomicBool::new(false)),
+                Options::default(),
+            );
+            search.start_search();
+
+            search.evaluation
+        };
+
+        let mate_in_two = "k7/8/2K5/8/8/8/8/7R w - - 0 1";
+
+        assert!(run(mate_in_two) == Eval::MATE_SCORE - 3, "expected mate in 2 with an empty table");
+        assert!(run(mate_in_two) == Eval::MATE_SCORE - 3, "expected mate in 2 with a filled table");
+        assert!(
+            run("1k6/8/1K6/8/8/8/8/7R w - - 0 1") == Eval::MATE_SCORE - 1,
+            "expected mate in 1 two plies later from the same table"
+        );
+    }
+
+    #[test]
+    fn mate_scores_are_reported_in_moves() {
+        assert!(SearchInfo::mate_in_moves(Eval::MATE_SCORE - 1) == 1);
+        assert!(SearchInfo::mate_in_moves(Eval::MATE_SCORE - 3) == 2);
+        assert!(SearchInfo::mate_in_moves(Eval::MATE_SCORE - 5) == 3);
+        assert!(
+            SearchInfo::mate_in_moves(-Eval::MATE_SCORE + 2) == -1,
+            "expected getting mated after our move and the mating reply to be mate -1"
+        );
+        assert!(SearchInfo::mate_in_moves(-Eval::MATE_SCORE + 4) == -2);
+    }
+
+    #[test]
+    fn multi_pv_reports_distinct_lines() {
+        let mut search = search_with(
+            Board::default(),
+            SearchLimits {
+                depth: Some(3),
+                ..Default::default()
+            },
+            Options {
+                multi_pv: 3,
+                ..Default::default()
+            },
+        );
+        search.start_search();
+
+        let first_moves = search.lines.iter().map(|(_, pv)| pv[0]).collect::<Vec<ChessMove>>();
+
+        assert!(search.lines.len() == 3, "expected three lines");
+        assert!(
+            first_moves[0] != first_moves[1] && first_moves[1] != first_moves[2] && first_moves[0] != first_moves[2],
+            "expected every line to start with a different move"
+        );
+        assert!(search.line_info(3, 2).to_info().to_string().contains("multipv 3"));
+    }
+
+    #[test]
+    fn history_is_aged_once_per_depth() {
+        let mut search = search_with(
+            Board::default(),
+            SearchLimits {
+                depth: Some(3),
+                ..Default::default()
+            },
+            Options {
+                multi_pv: 3,
+                ..Default::default()
+            },
+        );
+        // A move the search never plays, so only aging touches it.
+        let unplayed = ChessMove::new(Square::H1, Square::H8);
+        search.move_sorter.lock().unwrap().update_history(Color::White, unplayed, 1024);
+        search.start_search();
+
+        let history = search.move_sorter.lock().unwrap().history[Color::White as usize][Square::H1.to_index()]
+            [Square::H8.to_index()];
+        assert!(history == 128, "expected one halving for each of the three depths, got {}", history);
+    }
+
+    #[test]
+    fn multi_pv_is_capped_by_the_legal_moves() {
+        let mut search = search_with(
+            Board::from_fen("k7/8/1K6/8/8/8/8/8 b - - 0 1"),
+            SearchLimits {
+                depth: Some(2),
+                ..Default::default()
+            },
+            Options {
+                multi_pv: 5,
+                ..Default::default()
+            },
+        );
+        search.start_search();
+
+        assert!(search.lines.len() == 1, "expected one line for the only legal move");
+    }
+
+    #[test]
+    fn fixed_depth_search_is_reproducible() {
+        let fen = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+
+        let mut first = search(fen, 5);
+        first.start_search();
+
+        let mut second = search(fen, 5);
+        second.start_search();
+
+        assert!(
+            first.nodes == second.nodes && first.pv == second.pv,
+            "expected a fixed depth search with fresh tables to repeat exactly"
+        );
+    }
+
+    #[test]
+    fn futility_margin_grows_with_depth() {
+        assert!(Search::futile(0, 150, 1), "expected a quiet move needing 150cp at depth 1 to be futile");
+        assert!(!Search::futile(0, 150, 2), "expected the same move to be searched at depth 2");
+    }
+
+    #[test]
+    fn futile_quiets_still_fail_low() {
+        let mut search = search("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", 2);
+        let board = search.board;
+
+        let score = search.search(&board, 999, 1000, 2, 1);
+
+        assert!(score <= 999, "expected a hopeless null window to fail low after pruning quiets");
+    }
+
+    #[test]
+    fn revisited_positions_reuse_the_static_eval() {
+        let mut search = search("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 4);
+        let board = search.board;
+
+        let first = search.evaluate(&board);
+        let second = search.evaluate(&board);
+
+        assert!(first == second && search.eval_cache_hits == 1, "expected the second evaluation to be cached");
+
+        search.start_search();
+        assert!(search.eval_cache_hits > 1, "expected transpositions in the search to hit the cache");
+    }
+
+    #[test]
+    fn internal_iterative_deepening_finds_a_first_move() {
+        let mut search = search("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", 6);
+        let board = search.board;
+
+        let mv = search.internal_iterative_deepening(&board, -INFINITY, INFINITY, Search::IID_DEPTH, 0);
+
+        assert!(
+            mv != ChessMove::NULL_MOVE && board.make_move_new(mv).is_ok(),
+            "expected a legal move from the shallower search on a cold table"
+        );
+    }
+
+    #[test]
+    fn quiescence_skips_losing_captures() {
+        // Every capture the queen has runs into a pawn recapture.
+        let fen = "7k/8/1pp1pp2/2ppp3/3Q4/8/8/K7 w - - 0 1";
+        let mut search = search(fen, 4);
+        let board = search.board;
+
+        search.search_captures(&board, -INFINITY, INFINITY, 0, 0);
+
+        assert!(search.see_prunes == 3, "expected all three queen captures to be pruned");
+        assert!(search.nodes == 1, "expected quiescence to stand pat without searching a capture");
+
+        let mut unpruned = self::search(fen, 4);
+        unpruned.see_pruning = false;
+        unpruned.search_captures(&board, -INFINITY, INFINITY, 0, 0);
+        assert!(unpruned.nodes > 1, "expected the captures to be searched without SEE pruning");
+
+        let best_move = search.start_search().unwrap();
+        let root_nodes = search.nodes;
+        assert!(
+            !(board.get_piece(best_move.from) == Some(Piece::Queen) && board.get_piece(best_move.to).is_some()),
+            "expected the queen to keep out of the pawn chain, got {best_move}"
+        );
+
+        let mut unpruned = self::search(fen, 4);
+        unpruned.see_pruning = false;
+        assert!(
+            unpruned.start_search() == Some(best_move),
+            "expected the same best move without SEE pruning"
+        );
+        assert!(
+            root_nodes < unpruned.nodes,
+            "expected SEE pruning to save nodes, got {} against {}",
+            root_nodes,
+            unpruned.nodes
+        );
+    }
+
+    #[test]
+    fn razoring_drops_hopeless_pre_leaf_nodes_into_quiescence() {
+        let mut search = search("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 1);
+        let board = search.board;
+
+        let score = search.search(&board, 500, 501, 1, 1);
+
+        assert!(score <= 500, "expected the node to fail low");
+        assert!(search.razor_cutoffs == 1, "expected the node to be razored");
+        assert!(search.nodes < 20, "expected quiescence instead of all twenty replies, got {} nodes", search.nodes);
+    }
+
+    #[test]
+    fn razoring_keeps_the_best_move() {
+        // Black left the queen hanging, razoring must not hide the capture.
+        let mut search = search("rnb1kbnr/pppp1ppp/8/4p1q1/4P3/3P4/PPP2PPP/RNBQKBNR w KQkq - 0 3", 4);
+
+        let mv = search.start_search().unwrap();
+
+        assert!(mv == ChessMove::new(Square::C1, Square::G5), "expected Bxg5, got {mv}");
+        assert!(search.razor_cutoffs > 0, "expected some razored nodes in a lopsided position");
+    }
+
+    #[test]
+    fn singular_extension_fires_on_the_only_good_move() {
+        // Rxd4 wins the queen, every other move leaves white a queen for a rook down.
+        let mut search = search("7k/8/8/8/3q4/8/8/3RK3 w - - 0 1", 8);
+        let board = search.board;
+        let capture = ChessMove::new(Square::D1, Square::D4);
+
+        let extension = search.singular_extension(&board, capture, 500, Search::SINGULAR_DEPTH, 0);
+
+        assert!(extension == 1, "expected the only good move to be extended");
+        assert!(search.singular_extensions == 1, "expected the extension to be counted");
+    }
+
+    #[test]
+    fn singular_extension_skips_moves_with_equal_alternatives() {
+        let mut search = search("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 8);
+        let board = search.board;
+        let mv = ChessMove::new(Square::E2, Square::E4);
+
+        let extension = search.singular_extension(&board, mv, 0, Search::SINGULAR_DEPTH, 0);
+
+        assert!(extension == 0, "expected no extension when other opening moves hold the score");
+    }
+
+    #[test]
+    fn currmove_is_only_reported_in_long_searches() {
+        let mut search = search("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 1);
+        let mv = ChessMove::new(Square::E2, Square::E4);
+
+        search.think_timer = Instant::now();
+        assert!(search.currmove_info(mv, 3, 8).is_none(), "expected no currmove right after starting");
+
+        search.think_timer = Instant::now() - Duration::from_secs(2);
+        let info = search.currmove_info(mv, 3, 8).unwrap().to_string();
+
+        assert!(
+            info.contains("currmove e2e4") && info.contains("currmovenumber 3"),
+            "expected the current root move and its number after a second"
+        );
+    }
+
+    #[test]
+    fn contempt_biases_repetitions_against_the_root_side() {
+        let fen = "4k3/8/8/8/8/8/8/3QK3 w - - 0 1";
+        let board = Board::from_fen(fen);
+        let repeated = board.make_move_new(ChessMove::new(Square::E1, Square::F1)).unwrap();
+
+        for contempt in [20, -20] {
+            let mut search = Search::new(
+                board,
+                SearchLimits::default(),
+                vec![repeated.hash(), repeated.hash()],
+                Arc::new(TranspositionTable::with_size_mb(1)),
+                Arc::new(Mutex::new(MoveSorter::new())),
+                Arc::new(AtomicBool::new(false)),
+                Options {
+                    contempt,
+                    ..Default::default()
+                },
+            );
+
+            let score = search.search(&repeated, -INFINITY, INFINITY, 3, 1);
+
+            assert!(
+                score == contempt,
+                "expected the opponent to score a repetition as {} with contempt {}",
+                contempt,
+                contempt
+            );
+        }
+    }
+
+    #[test]
+    fn helper_threads_fill_the_shared_table_quietly() {
+        let transposition_table = Arc::new(TranspositionTable::with_size_mb(1));
+
+        let mut helper = Search::new(
+            Board::default(),
+            SearchLimits {
+                depth: Some(4),
+                ..Default::default()
+            },
+            Vec::new(),
+            transposition_table.clone(),
+            Arc::new(Mutex::new(MoveSorter::new())),
+            Arc::new(AtomicBool::new(false)),
+            Options::default(),
+        );
+        helper.thread_id = 1;
+
+        let mv = helper.start_search().unwrap();
+
+        assert!(!helper.is_main_thread());
+        assert!(Board::default().make_move_new(mv).is_ok(), "expected a legal move from a helper");
+        assert!(
+            transposition_table.entries().iter().any(|entry| entry.bound != Bound::None),
+            "expected the helper to store its results in the shared table"
+        );
+    }
+
+    #[test]
+    fn limited_strength_sometimes_misses_the_tactic() {
+        // dxe5 wins a knight, which a weak player should not always see.
+        let board = Board::from_fen("rnbqkb1r/pppppppp/8/4n3/3P4/8/PPP1PPPP/RNBQKBNR w KQkq - 0 1");
+        let capture = ChessMove::new(Square::D4, Square::E5);
+
+        let moves = (1..=20)
+            .map(|random_seed| {
+                search_with(
+                    board,
+                    SearchLimits {
+                        depth: Some(6),
+                        ..Default::default()
+                    },
+                    Options {
+                        limit_strength: true,
+                        elo: 500,
+                        random_seed,
+                        ..Default::default()
+                    },
+                )
+                .start_search()
+                .unwrap()
+            })
+            .collect::<Vec<ChessMove>>();
+
+        assert!(moves.iter().all(|&mv| board.make_move_new(mv).is_ok()), "expected only legal moves");
+        assert!(moves.iter().any(|&mv| mv != capture), "expected a weak player to miss the capture sometimes");
+        assert!(
+            search("rnbqkb1r/pppppppp/8/4n3/3P4/8/PPP1PPPP/RNBQKBNR w KQkq - 0 1", 4).start_search() == Some(capture),
+            "expected full strength to always take the knight"
+        );
+    }
+
+    #[test]
+    fn eval_noise_varies_the_opening_but_keeps_the_tactic() {
+        let noisy = |fen: &str, random_seed: u64| {
+            search_with(
+                Board::from_fen(fen),
+                SearchLimits {
+                    depth: Some(4),
+                    ..Default::default()
+                },
+                Options {
+                    eval_noise: 10,
+                    random_seed,
+                    ..Default::default()
+                },
+            )
+            .start_search()
+            .unwrap()
+        };
+
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let mut openings = (1..=12).map(|random_seed| noisy(start, random_seed)).collect::<Vec<ChessMove>>();
+        openings.dedup();
+        assert!(openings.len() > 1, "expected different first moves across seeds");
+
+        let tactic = "rnbqkb1r/pppppppp/8/4n3/3P4/8/PPP1PPPP/RNBQKBNR w KQkq - 0 1";
+        assert!(
+            (1..=12).all(|random_seed| noisy(tactic, random_seed) == ChessMove::new(Square::D4, Square::E5)),
+            "expected the noise to never give up a free knight"
+        );
+    }
+
+    #[test]
+    fn failed_aspiration_windows_report_bounds() {
+        let mut lowerbounds = 0;
+
+        for fen in crate::bench::BENCH_FENS {
+            let mut search = search(fen, 7);
+            search.start_search();
+
+            lowerbounds += search.reported.iter().filter(|info| info.contains("lowerbound")).count();
+            assert!(
+                !search.reported.last().unwrap().contains("bound"),
+                "expected the completed iteration to report an exact score in {}",
+                fen
+            );
+        }
+
+        assert!(lowerbounds > 0, "expected a fail high to be reported as a lowerbound");
+    }
+
+    #[test]
+    fn continuation_history_moves_cutoffs_to_the_first_move() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+            "rnb1kbnr/pppp1ppp/8/4p1q1/4P3/3P4/PPP2PPP/RNBQKBNR w KQkq - 0 3",
+        ];
+
+        // First move cutoffs and all cutoffs over the whole suite.
+        let cutoffs = |use_continuation_history: bool| {
+            fens.iter().fold((0, 0), |(first_move_cutoffs, cutoffs), fen| {
+                let mut search = search(fen, 6);
+                search.move_sorter.lock().unwrap().use_continuation_history = use_continuation_history;
+                search.start_search();
+
+                (first_move_cutoffs + search.first_move_cutoffs, cutoffs + search.cutoffs)
+            })
+        };
+
+        let (with_first, with_all) = cutoffs(true);
+        let (without_first, without_all) = cutoffs(false);
+
+        assert!(
+            with_first * without_all >= without_first * with_all,
+            "expected a larger share of first move cutoffs with continuation history, got {}/{} against {}/{}",
+            with_first,
+            with_all,
+            without_first,
+            without_all
+        );
+    }
+
+    #[test]
+    fn corrupted_pv_is_cut_at_the_first_illegal_move() {
+        let mut search = search("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 1);
+        let e4 = ChessMove::new(Square::E2, Square::E4);
+        let e5 = ChessMove::new(Square::E7, Square::E5);
+
+        // The second e2e4 has no pawn left to move.
+        search.pv = vec![e4, e5, e4, ChessMove::new(Square::G1, Square::F3)];
+
+        assert!(Search::legal_prefix(&search.board, &search.pv) == vec![e4, e5]);
+
+        search.report(search.search_info(1));
+        assert!(
+            search.reported[0].contains("pv e2e4 e7e5") && !search.reported[0].contains("g1f3"),
+            "expected the reported line to stop before the illegal move, got {}",
+            search.reported[0]
+        );
+    }
+
+    #[test]
+    fn principal_variation_is_written_in_place() {
+        let mut search = search("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 5);
+
+        // What the Vec based collection built, each node's move followed by its child's line.
+        let line = [
+            ChessMove::new(Square::E2, Square::A6),
+            ChessMove::new(Square::B4, Square::C3),
+            ChessMove::new(Square::D2, Square::C3),
+        ];
+        search.pv_length[line.len()] = 0;
+        for (ply, &mv) in line.iter().enumerate().rev() {
+            search.update_pv(ply as u8, mv);
+        }
+        assert!(search.principal_variation(0) == line, "expected the root row to hold the whole line");
+        assert!(search.principal_variation(1) == &line[1..], "expected each row to hold the line below its node");
+
+        search.start_search();
+
+        let root_entry = search.transposition_table.probe(search.board.hash()).unwrap();
+        assert!(search.principal_variation(0) == search.pv.as_slice(), "expected the root row to be the reported line");
+        assert!(root_entry.mv == search.pv[0], "expected the line to start with the move stored for the root");
+
+        let mut board = search.board;
+        for &mv in &search.pv {
+            board = board.make_move_new(mv).expect("expected every PV move to be legal in sequence");
+        }
+        assert!(search.pv.len() > 1, "expected more than the best move in the line");
+    }
+
+    #[test]
+    fn node_limited_search_stops_near_the_budget() {
+        let mut search = search_with(
+            Board::default(),
+            SearchLimits {
+                nodes: Some(20_000),
+                ..Default::default()
+            },
+            Options::default(),
+        );
+
+        let mv = search.start_search().unwrap();
+
+        assert!(Board::default().make_move_new(mv).is_ok(), "expected a legal move within the budget");
+        assert!(
+            search.nodes >= 20_000 && search.nodes < 25_000,
+            "expected the search to stop close to 20000 nodes, got {}",
+            search.nodes
+        );
+    }
+
+    #[test]
+    fn root_prefers_a_win_over_a_repetition() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1");
+        let repeating = ChessMove::new(Square::E1, Square::F1);
+        let repeated = board.make_move_new(repeating).unwrap();
+
+        let mut search = Search::new(
+            board,
+            SearchLimits::default(),
+            vec![repeated.hash(), repeated.hash()],
+            Arc::new(TranspositionTable::with_size_mb(1)),
+            Arc::new(Mutex::new(MoveSorter::new())),
+            Arc::new(AtomicBool::new(false)),
+            Options {
+                contempt: -100,
+                ..Default::default()
+            },
+        );
+
+        search.search_base(-INFINITY, INFINITY, 3, 0);
+
+        let (_, repetition_score) = search
+            .root_scores_iteration
+            .iter()
+            .copied()
+            .find(|(mv, _)| *mv == repeating)
+            .unwrap();
+
+        assert!(repetition_score <= 0, "expected the repetition to never look better than a draw");
+        assert!(search.pv_iteration[0] != repeating, "expected the winning queen moves over the repetition");
+    }
+
+    #[test]
+    fn search_moves_restrict_the_root() {
+        let mut search = search_with(
+            Board::default(),
+            SearchLimits {
+                depth: Some(3),
+                search_moves: vec!["a2a3".to_string(), "e2e5".to_string()],
+                ..Default::default()
+            },
+            Options::default(),
+        );
+
+        assert!(search.search_moves.len() == 1, "expected the illegal e2e5 to be ignored");
+        assert!(
+            search.start_search() == Some(ChessMove::new(Square::A2, Square::A3)),
+            "expected the only allowed move to be played"
+        );
+    }
+
+    #[test]
+    fn go_mate_stops_once_the_mate_is_proven() {
+        let mut search = search_with(
+            Board::from_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1"),
+            SearchLimits {
+                mate: Some(2),
+                ..Default::default()
+            },
+            Options::default(),
+        );
+
+        let best_move = search.start_search().unwrap();
+
+        assert!(search.mate_found() && search.root_depth <= 3, "expected the mate in 2 within 3 plies");
+        assert!(
+            best_move == ChessMove::new(Square::C6, Square::B6) || best_move == ChessMove::new(Square::C6, Square::C7),
+            "expected one of the two mating king moves"
+        );
+        assert!(search.search_info(search.root_depth).to_info().to_string().contains("mate 2"));
+    }
+
+    #[test]
+    fn mate_distance_pruning_collapses_the_window() {
+        let mut search = search("k7/8/2K5/8/8/8/8/7R w - - 0 1", 5);
+        let board = search.board;
+
+        let score = search.search(&board, Eval::MATE_SCORE - 2, Eval::MATE_SCORE - 1, 5, 1);
+
+        assert!(
+            score == Eval::MATE_SCORE - 2 && search.nodes == 1,
+            "expected no search when a faster mate than possible is already known"
+        );
+    }
+
+    #[test]
+    fn shortest_mate_wins_despite_bad_move_ordering() {
+        let move_sorter = Arc::new(Mutex::new(MoveSorter::new()));
+        for to in [Square::H2, Square::H3, Square::H4, Square::H5, Square::H6, Square::G1, Square::F1] {
+            move_sorter.lock().unwrap().update_history(Color::White, ChessMove::new(Square::H1, to), 10_000);
+        }
+
+        let mut search = Search::new(
+            Board::from_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1"),
+            SearchLimits {
+                depth: Some(6),
+                ..Default::default()
+            },
+            Vec::new(),
+            Arc::new(TranspositionTable::with_size_mb(1)),
+            move_sorter,
+            Arc::new(AtomicBool::new(false)),
+            Options::default(),
+        );
+        search.start_search();
+
+        assert!(
+            search.evaluation == Eval::MATE_SCORE - 3,
+            "expected mate in 2 even with slow rook moves ordered first"
+        );
+    }
+
+    #[test]
+    fn cutoffs_are_stored_under_the_node_hash() {
+        let mut search = search("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", 3);
+        let board = search.board;
+
+        let score = search.search(&board, -2000, -1000, 3, 1);
+        assert!(score >= -1000, "expected the window far below the eval to fail high");
+
+        let entry = search.transposition_table.probe(board.hash());
+        assert!(
+            entry.is_some_and(|entry| {
+                entry.bound == Bound::Lower && board.make_move_new(entry.best_move(&board)).is_ok()
+            }),
+            "expected the cutoff to be stored for the node with one of its own moves"
+        );
+    }
+
+    #[test]
+    fn null_move_is_skipped_with_only_king_and_pawns() {
+        let pawns_only = Board::from_fen("4k3/4p3/8/8/8/8/4P3/4K3 w - - 0 1");
+        let with_rook = Board::from_fen("4k3/4p3/8/8/8/8/4P3/R3K3 w - - 0 1");
+
+        assert!(
+            !Search::has_non_pawn_material(&pawns_only),
+            "expected a king and pawns to count as zugzwang prone"
+        );
+        assert!(
+            Search::has_non_pawn_material(&with_rook),
+            "expected a rook to allow null move pruning"
+        );
+    }
+
+    #[test]
+    fn reductions_grow_with_depth_and_move_count() {
+        for depth in 1..32 {
+            for moves in 1..32 {
+                assert!(REDUCTIONS[depth][moves] >= 1, "expected every late move to be reduced");
+
+                if depth > 1 {
+                    assert!(REDUCTIONS[depth][moves] >= REDUCTIONS[depth - 1][moves]);
+                }
+                if moves > 1 {
+                    assert!(REDUCTIONS[depth][moves] >= REDUCTIONS[depth][moves - 1]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pick_root_move_is_deterministic_with_a_fixed_seed() {
+        let root_scores = [
+            (ChessMove::new(Square::E2, Square::E4), 30),
+            (ChessMove::new(Square::D2, Square::D4), 28),
+            (ChessMove::new(Square::G1, Square::F3), 25),
+            (ChessMove::new(Square::A2, Square::A3), -40),
+        ];
+
+        let mut first = Random::new(42);
+        let mut second = Random::new(42);
+
+        for _ in 0..32 {
+            assert!(
+                Search::pick_root_move(&root_scores, 10, &mut first)
+                    == Search::pick_root_move(&root_scores, 10, &mut second),
+                "expected the same seed to pick the same moves"
+            );
+        }
+    }
+
+    #[test]
+    fn pick_root_move_only_picks_near_equal_moves() {
+        let root_scores = [
+            (ChessMove::new(Square::E2, Square::E4), 30),
+            (ChessMove::new(Square::D2, Square::D4), 28),
+            (ChessMove::new(Square::A2, Square::A3), -40),
+        ];
+
+        let mut random = Random::new(7);
+
+        for _ in 0..64 {
+            let mv = Search::pick_root_move(&root_scores, 10, &mut random).unwrap();
+
+            assert!(
+                mv != ChessMove::new(Square::A2, Square::A3),
+                "expected a move outside the margin to never be picked"
+            );
+        }
+    }
+}
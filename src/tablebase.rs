@@ -0,0 +1,117 @@
+// Without the syzygy feature nothing can be probed, so most of this is never used.
+#![cfg_attr(not(feature = "syzygy"), allow(dead_code))]
+
+use chessframe::{board::Board, chess_move::ChessMove};
+#[cfg(feature = "syzygy")]
+use shakmaty::{CastlingMode, Chess, fen::Fen};
+
+use crate::eval::Eval;
+
+// Below every mate score and above every evaluation, less the plies it takes to reach the position.
+pub const TB_WIN_SCORE: i32 = Eval::MATE_SCORE - 2000;
+
+// Cursed wins and blessed losses count as draws, the fifty move rule gets there first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wdl {
+    Loss,
+    Draw,
+    Win,
+}
+
+pub struct Tablebases {
+    #[cfg(feature = "syzygy")]
+    tables: shakmaty_syzygy::Tablebase<Chess>,
+}
+
+#[cfg(feature = "syzygy")]
+impl Tablebases {
+    // Directories are separated like in PATH, which is how GUIs pass SyzygyPath to other engines too.
+    pub fn open(path: &str) -> Result<Tablebases, String> {
+        let mut tables = shakmaty_syzygy::Tablebase::new();
+
+        for directory in std::env::split_paths(path) {
+            tables
+                .add_directory(&directory)
+                .map_err(|error| format!("{}: {}", directory.display(), error))?;
+        }
+
+        Ok(Tablebases { tables })
+    }
+
+    pub fn max_pieces(&self) -> usize {
+        self.tables.max_pieces()
+    }
+
+    fn position(board: &Board) -> Option<Chess> {
+        board.to_fen().parse::<Fen>().ok()?.into_position(CastlingMode::Standard).ok()
+    }
+
+    // Only exact right after a capture or pawn move, later on the fifty move counter may change the result.
+    pub fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+        let wdl = self.tables.probe_wdl_after_zeroing(&Self::position(board)?).ok()?;
+
+        Some(match wdl {
+            shakmaty_syzygy::Wdl::Win => Wdl::Win,
+            shakmaty_syzygy::Wdl::Loss => Wdl::Loss,
+            _ => Wdl::Draw,
+        })
+    }
+
+    // The move that keeps the best result with the fewest moves to the next capture or pawn move.
+    pub fn best_move(&self, board: &Board) -> Option<ChessMove> {
+        let (mv, _) = self.tables.best_move(&Self::position(board)?).ok()??;
+
+        board.infer_move(&mv.to_uci(CastlingMode::Standard).to_string()).ok()
+    }
+}
+
+#[cfg(not(feature = "syzygy"))]
+impl Tablebases {
+    pub fn open(_path: &str) -> Result<Tablebases, String> {
+        Err("ferrischess was built without the syzygy feature".to_string())
+    }
+
+    pub fn max_pieces(&self) -> usize {
+        0
+    }
+
+    pub fn probe_wdl(&self, _board: &Board) -> Option<Wdl> {
+        None
+    }
+
+    pub fn best_move(&self, _board: &Board) -> Option<ChessMove> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Needs the three to five piece tables in the directories listed in SYZYGY_PATH.
+    #[test]
+    #[cfg(feature = "syzygy")]
+    fn queen_against_king_is_won_and_converges() {
+        let Ok(path) = std::env::var("SYZYGY_PATH") else {
+            return;
+        };
+        let tablebases = Tablebases::open(&path).unwrap();
+        let board = Board::from_fen("8/8/8/4k3/8/8/8/KQ6 w - - 0 1");
+
+        assert!(tablebases.probe_wdl(&board) == Some(Wdl::Win), "expected the queen to win");
+
+        let best_move = tablebases.best_move(&board).unwrap();
+        let next_board = board.make_move_new(best_move).unwrap();
+        assert!(
+            tablebases.probe_wdl(&next_board) == Some(Wdl::Loss),
+            "expected {} to keep the win",
+            best_move
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "syzygy"))]
+    fn tablebases_need_the_syzygy_feature() {
+        assert!(Tablebases::open("/tmp").is_err(), "expected probing to be unavailable without the feature");
+    }
+}
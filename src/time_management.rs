@@ -1,11 +1,19 @@
 use std::{sync::{Arc, atomic::{AtomicBool, Ordering}}, time::Instant};
 
+// A best move that survived this many iterations in a row is taken as an easy move.
+pub const STABLE_ITERATIONS: usize = 4;
+// Percentage of the allotment an easy move gets before the search stops deepening.
+pub const EASY_MOVE_PERCENT: usize = 30;
+// Milliseconds every move gets at least, however much overhead there is.
+pub const MIN_THINK_TIME: usize = 10;
+
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Hash, Default)]
 pub enum TimeManagement {
     #[default]
     None,
     MoveTime { time: usize },
-    TimeLeft { time: usize },
+    // `time` is what a move normally gets, `max_time` what it may grow to when the search is in trouble.
+    TimeLeft { time: usize, max_time: usize },
 }
 
 impl TimeManagement {
@@ -13,20 +21,41 @@ impl TimeManagement {
         move_time: Option<usize>,
         time: Option<usize>,
         time_inc: Option<usize>,
+        moves_to_go: Option<usize>,
     ) -> TimeManagement {
         if let Some(move_time) = move_time {
             TimeManagement::MoveTime {
                 time: move_time.max(1),
             }
         } else if let Some(time) = time {
+            // Without movestogo assume 20 more moves, and never bet more than half the clock on one move.
+            let moves_to_go = moves_to_go.map_or(20, |moves_to_go| moves_to_go.max(1));
+            let allotment = (time / moves_to_go + time_inc.unwrap_or(0) / 2).min(time / 2).max(1);
+
             TimeManagement::TimeLeft {
-                time: (time / 20 + time_inc.unwrap_or(0) / 2).max(1),
+                time: allotment,
+                max_time: (3 * allotment).min(time / 2).max(allotment),
             }
         } else {
             TimeManagement::None
         }
     }
 
+    // The GUI's clock keeps running while the move travels, so the overhead comes off every limit,
+    // but a move still gets a little thought, unless it was given even less to begin with.
+    pub fn with_overhead(self, overhead: usize) -> TimeManagement {
+        let think = |time: usize| time.saturating_sub(overhead).max(MIN_THINK_TIME.min(time));
+
+        match self {
+            TimeManagement::MoveTime { time } => TimeManagement::MoveTime { time: think(time) },
+            TimeManagement::TimeLeft { time, max_time } => TimeManagement::TimeLeft {
+                time: think(time),
+                max_time: think(max_time),
+            },
+            TimeManagement::None => TimeManagement::None,
+        }
+    }
+
     pub fn should_cancel_search(&self, timer: Instant, cancelled: Arc<AtomicBool>) -> bool {
         if timer.elapsed().as_millis() as usize >= self.time()
             && *self != TimeManagement::None
@@ -36,10 +65,114 @@ impl TimeManagement {
         cancelled.load(Ordering::Relaxed)
     }
 
+    // Only a running clock is worth saving, a movetime search uses all of its time anyway.
+    pub fn stop_iterating(&self, elapsed: usize, stable_iterations: usize, root_moves: usize) -> bool {
+        let TimeManagement::TimeLeft { time, .. } = *self else {
+            return false;
+        };
+
+        root_moves == 1 || (stable_iterations >= STABLE_ITERATIONS && elapsed >= time * EASY_MOVE_PERCENT / 100)
+    }
+
+    // The rest of the allotment up to the hard limit, for when the best move was just refuted.
+    pub fn extend(&mut self) {
+        if let TimeManagement::TimeLeft { time, max_time } = self {
+            *time = *max_time;
+        }
+    }
+
     pub fn time(&self) -> usize {
         match self {
-            TimeManagement::MoveTime { time } | TimeManagement::TimeLeft { time } => *time,
+            TimeManagement::MoveTime { time } | TimeManagement::TimeLeft { time, .. } => *time,
             _ => 0,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn move_time_takes_precedence_over_the_clock() {
+        assert!(
+            TimeManagement::new(Some(100), Some(60_000), Some(1_000), None)
+                == TimeManagement::MoveTime { time: 100 }
+        );
+    }
+
+    #[test]
+    fn time_left_uses_a_twentieth_plus_half_the_increment() {
+        assert!(
+            TimeManagement::new(None, Some(60_000), Some(1_000), None)
+                == TimeManagement::TimeLeft { time: 3_500, max_time: 10_500 }
+        );
+        assert!(TimeManagement::new(None, None, None, None) == TimeManagement::None);
+    }
+
+    #[test]
+    fn moves_to_go_splits_the_clock() {
+        assert!(
+            TimeManagement::new(None, Some(60_000), None, Some(10))
+                == TimeManagement::TimeLeft { time: 6_000, max_time: 18_000 },
+            "expected the clock to be split over the remaining moves"
+        );
+        assert!(
+            TimeManagement::new(None, Some(60_000), None, Some(40))
+                == TimeManagement::TimeLeft { time: 1_500, max_time: 4_500 },
+            "expected a distant time control to be split over all of its moves"
+        );
+        assert!(
+            TimeManagement::new(None, Some(60_000), None, Some(1))
+                == TimeManagement::TimeLeft { time: 30_000, max_time: 30_000 },
+            "expected the last move before the time control to keep a reserve"
+        );
+    }
+
+    #[test]
+    fn overhead_comes_off_the_allotment() {
+        let clock = TimeManagement::new(None, Some(60_000), None, None);
+
+        assert!(
+            clock.with_overhead(500) == TimeManagement::TimeLeft { time: 2_500, max_time: 8_500 },
+            "expected the overhead to come off both limits"
+        );
+        assert!(
+            clock.with_overhead(5_000) == TimeManagement::TimeLeft { time: MIN_THINK_TIME, max_time: 4_000 },
+            "expected a large overhead to leave the minimum think time"
+        );
+        assert!(
+            TimeManagement::MoveTime { time: 5 }.with_overhead(5_000) == TimeManagement::MoveTime { time: 5 },
+            "expected the floor to never exceed the time that was given"
+        );
+    }
+
+    #[test]
+    fn easy_moves_stop_the_clock_early() {
+        let clock = TimeManagement::new(None, Some(60_000), None, None);
+
+        assert!(clock.stop_iterating(0, 0, 1), "expected a single legal move to stop right away");
+        assert!(clock.stop_iterating(1_000, STABLE_ITERATIONS, 20), "expected a stable best move to stop early");
+        assert!(!clock.stop_iterating(100, STABLE_ITERATIONS, 20), "expected a minimum of thought first");
+        assert!(!clock.stop_iterating(1_000, 1, 20), "expected a changing best move to keep searching");
+        assert!(
+            !TimeManagement::MoveTime { time: 3_000 }.stop_iterating(1_000, STABLE_ITERATIONS, 1),
+            "expected a movetime search to use its time"
+        );
+    }
+
+    #[test]
+    fn extension_is_capped_by_the_hard_limit() {
+        let mut clock = TimeManagement::new(None, Some(60_000), None, None);
+        clock.extend();
+        assert!(clock.time() == 9_000, "expected the allotment to triple");
+
+        let mut last_moves = TimeManagement::new(None, Some(60_000), None, Some(2));
+        last_moves.extend();
+        assert!(last_moves.time() == 30_000, "expected never more than half the clock");
+
+        let mut move_time = TimeManagement::MoveTime { time: 100 };
+        move_time.extend();
+        assert!(move_time.time() == 100, "expected a movetime to stay fixed");
+    }
+}
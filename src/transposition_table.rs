@@ -1,4 +1,13 @@
-use chessframe::chess_move::ChessMove;
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+
+use chessframe::{
+    bitboard::BitBoard,
+    board::Board,
+    chess_move::ChessMove,
+    file::File,
+    rank::Rank,
+    square::Square,
+};
 
 use crate::eval::Eval;
 
@@ -11,13 +20,22 @@ pub enum Bound {
     Lower,
 }
 
-#[derive(Clone, Copy, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Hash, Default)]
+pub enum Replacement {
+    #[default]
+    DepthPreferred,
+    Always,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct Entry {
     pub zobrist: u64,
     pub depth: u8,
     pub score: i32,
-    pub mv: ChessMove,
+    // Packed, a promotion can only be rebuilt from the moves of the position.
+    mv: u16,
     pub bound: Bound,
+    pub generation: u8,
 }
 
 impl Entry {
@@ -27,14 +45,111 @@ impl Entry {
             depth: 0,
             score: 0,
             bound: Bound::None,
-            mv: ChessMove::NULL_MOVE,
+            mv: 0,
+            generation: 0,
+        }
+    }
+
+    // 6 bits each for the squares, the promoted piece plus one above them and a flag for a real move.
+    fn pack_move(mv: ChessMove) -> u16 {
+        if mv == ChessMove::NULL_MOVE {
+            return 0;
+        }
+
+        let promotion = mv.promotion().map_or(0, |piece| piece.to_index() as u16 + 1);
+
+        (1 << 15) | (promotion << 12) | ((mv.to.to_index() as u16) << 6) | mv.from.to_index() as u16
+    }
+
+    pub fn best_move(&self, board: &Board) -> ChessMove {
+        if self.mv == 0 {
+            return ChessMove::NULL_MOVE;
+        }
+
+        let square = |index: u16| {
+            Square::make_square(Rank::from_index(index as usize / 8), File::from_index(index as usize % 8))
+        };
+        let from = square(self.mv & 63);
+        let to = square((self.mv >> 6) & 63);
+        let promotion = (self.mv >> 12) & 7;
+
+        if promotion == 0 {
+            return ChessMove::new(from, to);
+        }
+
+        board
+            .generate_moves_vec(BitBoard(1 << to.to_index()))
+            .into_iter()
+            .find(|mv| mv.from == from && mv.promotion().map(|piece| piece.to_index() as u16 + 1) == Some(promotion))
+            .unwrap_or(ChessMove::NULL_MOVE)
+    }
+
+    // Score, move, depth, generation and bound in one word, so a slot is two atomic stores.
+    fn to_data(self) -> u64 {
+        let bound = match self.bound {
+            Bound::None => 0,
+            Bound::Exact => 1,
+            Bound::Upper => 2,
+            Bound::Lower => 3,
+        };
+
+        self.score as u32 as u64
+            | ((self.mv as u64) << 32)
+            | ((self.depth as u64) << 48)
+            | (((self.generation & GENERATION_MASK) as u64) << 56)
+            | (bound << 62)
+    }
+
+    fn from_data(zobrist: u64, data: u64) -> Entry {
+        Entry {
+            zobrist,
+            score: data as u32 as i32,
+            mv: (data >> 32) as u16,
+            depth: (data >> 48) as u8,
+            generation: (data >> 56) as u8 & GENERATION_MASK,
+            bound: match data >> 62 {
+                0 => Bound::None,
+                1 => Bound::Exact,
+                2 => Bound::Upper,
+                _ => Bound::Lower,
+            },
         }
     }
 }
 
+// The generation only has 6 bits of the data word.
+const GENERATION_MASK: u8 = 63;
+
+// The key is stored XORed with the data, so an entry torn by two threads writing at once fails its
+// key check instead of handing out a move or score of another position.
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+impl Slot {
+    fn load(&self) -> (u64, u64) {
+        let data = self.data.load(Ordering::Relaxed);
+
+        (self.key.load(Ordering::Relaxed) ^ data, data)
+    }
+
+    fn store(&self, entry: Entry) {
+        let data = entry.to_data();
+
+        self.key.store(entry.zobrist ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+}
+
+// Shared by all search threads without a lock.
 pub struct TranspositionTable {
-    entries: Vec<Entry>,
+    slots: Vec<Slot>,
     max_entries: usize,
+    replacement: Replacement,
+    // Bumped for every search, entries from older searches are the first to go.
+    generation: AtomicU8,
 }
 
 impl TranspositionTable {
@@ -42,59 +157,176 @@ impl TranspositionTable {
         let size = num_entries.next_power_of_two();
 
         TranspositionTable {
-            entries: vec![Entry::empty(); size],
+            slots: (0..size).map(|_| Slot::default()).collect(),
             max_entries: size,
+            replacement: Replacement::default(),
+            generation: AtomicU8::new(0),
         }
     }
 
     pub fn with_size_mb(size_mb: usize) -> TranspositionTable {
-        let entry_size = std::mem::size_of::<Entry>();
-        let num_entries = (size_mb * 1024 * 1024) / entry_size;
+        let slot_size = std::mem::size_of::<Slot>();
+        let num_entries = (size_mb * 1024 * 1024) / slot_size;
 
         Self::with_capacity(num_entries)
     }
 
+    // Per mille of the first thousand slots in use, like the UCI hashfull field wants.
+    pub fn hashfull(&self) -> usize {
+        let sample = &self.slots[..self.max_entries.min(1000)];
+        let used = sample.iter().filter(|slot| slot.load().1 != 0).count();
+
+        used * 1000 / sample.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.max_entries
+    }
+
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn generation(&self) -> u8 {
+        self.generation.load(Ordering::Relaxed) & GENERATION_MASK
+    }
+
+    pub fn set_replacement(&mut self, replacement: Replacement) {
+        self.replacement = replacement;
+    }
+
+    #[cfg(test)]
+    pub fn entries(&self) -> Vec<Entry> {
+        self.slots
+            .iter()
+            .map(|slot| match slot.load() {
+                (_, 0) => Entry::empty(),
+                (zobrist, data) => Entry::from_data(zobrist, data),
+            })
+            .collect()
+    }
+
+    // Entries stay valid between moves of one game, only a new game needs a clean table.
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
     fn index(&self, zobrist: u64) -> usize {
         (zobrist as usize) & (self.max_entries - 1)
     }
 
     pub fn store(&self, zobrist: u64, depth: u8, ply: u8, mut score: i32, mv: ChessMove, bound: Bound) {
-        let index = self.index(zobrist);
-
-        let entry = unsafe { self.entries.as_ptr().add(index) as *mut Entry };
+        let slot = &self.slots[self.index(zobrist)];
 
         if Eval::mate_score(score) {
             let sign = score.signum();
             score += sign * ply as i32;
         }
 
-        unsafe {
-            let replacement_entry = Entry {
+        let generation = self.generation();
+
+        let replace = match self.replacement {
+            Replacement::DepthPreferred => {
+                let (old_zobrist, data) = slot.load();
+                let old = Entry::from_data(old_zobrist, data);
+
+                old.bound == Bound::None || old.generation != generation || old.depth <= depth
+            }
+            Replacement::Always => true,
+        };
+
+        if replace {
+            slot.store(Entry {
                 zobrist,
                 depth,
                 score,
-                mv,
+                mv: Entry::pack_move(mv),
                 bound,
-            };
-
-            if (*entry).zobrist == zobrist {
-                if (*entry).depth <= depth {
-                    *entry = replacement_entry;
-                }
-            } else {
-                *entry = replacement_entry;
-            }
+                generation,
+            });
         }
     }
 
     pub fn probe(&self, zobrist: u64) -> Option<Entry> {
-        let index = self.index(zobrist);
+        let (entry_zobrist, data) = self.slots[self.index(zobrist)].load();
+
+        (data != 0 && entry_zobrist == zobrist).then(|| Entry::from_data(zobrist, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let entry = self.entries[index];
-        if entry != Entry::empty() && entry.zobrist == zobrist {
-            Some(entry)
-        } else {
-            None
+    #[test]
+    fn hashfull_grows_with_stores() {
+        let transposition_table = TranspositionTable::with_size_mb(1);
+        assert!(transposition_table.hashfull() == 0, "expected a fresh table to be empty");
+
+        for zobrist in 0..500 {
+            transposition_table.store(zobrist, 1, 0, 0, ChessMove::NULL_MOVE, Bound::Exact);
+        }
+        let half = transposition_table.hashfull();
+
+        for zobrist in 500..2000 {
+            transposition_table.store(zobrist, 1, 0, 0, ChessMove::NULL_MOVE, Bound::Exact);
         }
+
+        assert!(half > 0 && half < 1000, "expected a partly filled table");
+        assert!(transposition_table.hashfull() == 1000, "expected the sampled slots to be full");
+    }
+
+    #[test]
+    fn old_generations_are_evicted_before_deep_entries() {
+        let transposition_table = TranspositionTable::with_capacity(1);
+
+        transposition_table.store(1, 10, 0, 0, ChessMove::NULL_MOVE, Bound::Exact);
+        transposition_table.store(2, 1, 0, 0, ChessMove::NULL_MOVE, Bound::Exact);
+
+        assert!(
+            transposition_table.probe(1).is_some() && transposition_table.probe(2).is_none(),
+            "expected a deep entry of the current search to be kept"
+        );
+
+        transposition_table.new_search();
+        transposition_table.store(2, 1, 0, 0, ChessMove::NULL_MOVE, Bound::Exact);
+
+        assert!(
+            transposition_table.probe(1).is_none() && transposition_table.probe(2).is_some(),
+            "expected the entry of the previous search to be evicted"
+        );
+    }
+
+    #[test]
+    fn entries_round_trip_through_the_packed_slot() {
+        let board = Board::from_fen("8/1P6/8/8/8/8/8/k6K w - - 0 1");
+        let promotion = board.infer_move("b7b8n").unwrap();
+        let transposition_table = TranspositionTable::with_capacity(16);
+
+        transposition_table.store(board.hash(), 7, 0, -1234, promotion, Bound::Upper);
+        let entry = transposition_table.probe(board.hash()).unwrap();
+
+        assert!(entry.depth == 7 && entry.score == -1234 && entry.bound == Bound::Upper);
+        assert!(entry.best_move(&board) == promotion, "expected the underpromotion to come back");
+    }
+
+    #[test]
+    fn torn_entries_fail_the_key_check() {
+        let transposition_table = TranspositionTable::with_capacity(1);
+
+        transposition_table.store(1, 5, 0, 100, ChessMove::NULL_MOVE, Bound::Exact);
+        let key = transposition_table.slots[0].key.load(Ordering::Relaxed);
+
+        // Another thread's data lands between this entry's two stores.
+        transposition_table.store(2, 9, 0, -300, ChessMove::NULL_MOVE, Bound::Lower);
+        transposition_table.slots[0].key.store(key, Ordering::Relaxed);
+
+        assert!(
+            transposition_table.probe(1).is_none() && transposition_table.probe(2).is_none(),
+            "expected a key and data from different stores to match neither position"
+        );
     }
 }